@@ -0,0 +1,358 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::expr::Program;
+use crate::loss::Loss;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format
+{
+    // Header row of column names, then one example per row
+    Csv,
+    // Array of objects keyed by column name
+    Json,
+    // One object per line, blank lines are skipped
+    Jsonl
+}
+
+impl Format
+{
+    pub fn from_path(path: &Path) -> Option<Self>
+    {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str()
+        {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "jsonl" | "ndjson" => Some(Format::Jsonl),
+            _ => None
+        }
+    }
+}
+
+// Rows count examples from 1
+#[derive(Debug)]
+pub enum DatasetError
+{
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    UnknownFormat(String),
+    Empty,
+    NotAnObject {row: usize},
+    MissingColumn {row: usize, column: String},
+    NotANumber {row: usize, column: String, value: String}
+}
+
+impl fmt::Display for DatasetError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self
+        {
+            DatasetError::Io(error) => write!(f, "{}", error),
+            DatasetError::Csv(error) => write!(f, "{}", error),
+            DatasetError::Json(error) => write!(f, "{}", error),
+            DatasetError::UnknownFormat(path) => write!(f, "cannot tell the format of `{}` from its extension", path),
+            DatasetError::Empty => write!(f, "the dataset has no examples"),
+            DatasetError::NotAnObject {row} => write!(f, "row {} is not an object", row),
+            DatasetError::MissingColumn {row, column} => write!(f, "row {} has no column `{}`", row, column),
+            DatasetError::NotANumber {row, column, value} => write!(f, "row {}, column `{}`: `{}` is not a number", row, column, value)
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+impl From<std::io::Error> for DatasetError
+{
+    fn from(error: std::io::Error) -> Self
+    {
+        DatasetError::Io(error)
+    }
+}
+
+impl From<csv::Error> for DatasetError
+{
+    fn from(error: csv::Error) -> Self
+    {
+        DatasetError::Csv(error)
+    }
+}
+
+impl From<serde_json::Error> for DatasetError
+{
+    fn from(error: serde_json::Error) -> Self
+    {
+        DatasetError::Json(error)
+    }
+}
+
+// Input/output examples of an induction task. Booleans are read as 1 and 0.
+pub struct Dataset
+{
+    pub input_names: Vec<String>,
+    pub output_names: Vec<String>,
+    // One row per example, ordered like `input_names`
+    pub inputs: Vec<Vec<f32>>,
+    // One column per output, ordered like `output_names`
+    pub outputs: Vec<Vec<f32>>
+}
+
+impl Dataset
+{
+    // Reads the named input and output columns of a file, in the format its extension gives
+    pub fn load<P: AsRef<Path>>(path: P, inputs: &[&str], outputs: &[&str]) -> Result<Self, DatasetError>
+    {
+        let path = path.as_ref();
+        let format = Format::from_path(path).ok_or_else(|| DatasetError::UnknownFormat(path.display().to_string()))?;
+        Self::parse(&fs::read_to_string(path)?, format, inputs, outputs)
+    }
+
+    pub fn parse(text: &str, format: Format, inputs: &[&str], outputs: &[&str]) -> Result<Self, DatasetError>
+    {
+        let mut dataset = Self {
+            input_names: inputs.iter().map(|&name| name.to_owned()).collect(),
+            output_names: outputs.iter().map(|&name| name.to_owned()).collect(),
+            inputs: Vec::new(),
+            outputs: vec![Vec::new(); outputs.len()]
+        };
+
+        match format
+        {
+            Format::Csv =>
+            {
+                let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(text.as_bytes());
+                let headers = reader.headers()?.clone();
+                for (i, record) in reader.records().enumerate()
+                {
+                    let record = record?;
+                    dataset.push(i + 1, |column| {
+                        let index = headers.iter().position(|header| header == column)?;
+                        record.get(index).map(parse_text)
+                    })?;
+                }
+            }
+            Format::Json =>
+            {
+                let rows: Vec<Value> = serde_json::from_str(text)?;
+                for (i, row) in rows.iter().enumerate()
+                {
+                    dataset.push_object(i + 1, row)?;
+                }
+            }
+            Format::Jsonl =>
+            {
+                let lines = text.lines().filter(|line| !line.trim().is_empty());
+                for (i, line) in lines.enumerate()
+                {
+                    dataset.push_object(i + 1, &serde_json::from_str(line)?)?;
+                }
+            }
+        }
+
+        if dataset.inputs.is_empty()
+        {
+            return Err(DatasetError::Empty);
+        }
+
+        Ok(dataset)
+    }
+
+    // `value` gives the raw value of a column, parsed if it is a number or a boolean
+    fn push<F>(&mut self, row: usize, value: F) -> Result<(), DatasetError>
+    where
+        F: Fn(&str) -> Option<Result<f32, String>>
+    {
+        let read = |column: &String| match value(column)
+        {
+            Some(Ok(number)) => Ok(number),
+            Some(Err(value)) => Err(DatasetError::NotANumber {row, column: column.clone(), value}),
+            None => Err(DatasetError::MissingColumn {row, column: column.clone()})
+        };
+
+        let inputs = self.input_names.iter().map(read).collect::<Result<Vec<f32>, _>>()?;
+        let outputs = self.output_names.iter().map(read).collect::<Result<Vec<f32>, _>>()?;
+
+        self.inputs.push(inputs);
+        for (column, output) in self.outputs.iter_mut().zip(outputs)
+        {
+            column.push(output);
+        }
+        Ok(())
+    }
+
+    fn push_object(&mut self, row: usize, object: &Value) -> Result<(), DatasetError>
+    {
+        let object = object.as_object().ok_or(DatasetError::NotAnObject {row})?;
+        self.push(row, |column| object.get(column).map(parse_json))
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.inputs.is_empty()
+    }
+
+    // Input names in the form `Sketch::parse` takes its variables
+    pub fn variables(&self) -> Vec<&str>
+    {
+        self.input_names.iter().map(String::as_str).collect()
+    }
+
+    // Outputs of `program` on every example, its variables bound to the inputs of the same name
+    pub fn predictions(&self, program: &Program) -> Vec<f32>
+    {
+        let columns: Vec<usize> = program.variables.iter()
+            .map(|variable| {
+                self.input_names.iter()
+                    .position(|name| name == variable)
+                    .unwrap_or_else(|| panic!("the dataset has no input `{}`", variable))
+            })
+            .collect();
+
+        let mut values = vec![0.0; columns.len()];
+        self.inputs.iter()
+            .map(|row| {
+                for (value, &column) in values.iter_mut().zip(columns.iter())
+                {
+                    *value = row[column];
+                }
+                program.eval(&values)
+            })
+            .collect()
+    }
+
+    // Sum over the outputs of the loss of the program for that output
+    pub fn loss(&self, programs: &[Program], loss: &dyn Loss) -> f32
+    {
+        assert_eq!(programs.len(), self.outputs.len(), "need one program per output");
+        programs.iter()
+            .zip(self.outputs.iter())
+            .map(|(program, targets)| loss.loss(&self.predictions(program), targets))
+            .sum()
+    }
+}
+
+fn parse_text(value: &str) -> Result<f32, String>
+{
+    match value
+    {
+        "true" => Ok(1.0),
+        "false" => Ok(0.0),
+        _ => value.parse().map_err(|_| value.to_owned())
+    }
+}
+
+fn parse_json(value: &Value) -> Result<f32, String>
+{
+    match value
+    {
+        Value::Number(number) => number.as_f64().map(|number| number as f32).ok_or_else(|| number.to_string()),
+        Value::Bool(flag) => Ok(if *flag {1.0} else {0.0}),
+        Value::String(text) => parse_text(text),
+        other => Err(other.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn parse(text: &str, format: Format) -> Result<Dataset, DatasetError>
+    {
+        Dataset::parse(text, format, &["a", "b"], &["y"])
+    }
+
+    fn assert_examples(dataset: &Dataset)
+    {
+        assert_eq!(dataset.input_names, vec!["a", "b"]);
+        assert_eq!(dataset.output_names, vec!["y"]);
+        assert_eq!(dataset.inputs, vec![vec![1.0, 2.5], vec![-3.0, 1e3]]);
+        assert_eq!(dataset.outputs, vec![vec![0.5, 7.0]]);
+    }
+
+    #[test]
+    fn formats_read_the_same_examples()
+    {
+        // Columns are picked by name, whatever their order and whatever else is in the file
+        let csv = "y, b, a, note\n0.5, 2.5, 1, first\n7, 1e3, -3, second\n";
+        let json = r#"[{"a": 1, "b": 2.5, "y": 0.5, "note": "first"}, {"y": 7, "b": 1e3, "a": -3}]"#;
+        let jsonl = "{\"a\": 1, \"b\": 2.5, \"y\": 0.5}\n\n{\"a\": -3, \"b\": \"1000\", \"y\": 7}\n";
+
+        assert_examples(&parse(csv, Format::Csv).unwrap());
+        assert_examples(&parse(json, Format::Json).unwrap());
+        assert_examples(&parse(jsonl, Format::Jsonl).unwrap());
+    }
+
+    #[test]
+    fn format_follows_the_extension()
+    {
+        assert_eq!(Format::from_path(Path::new("data/simple.csv")), Some(Format::Csv));
+        assert_eq!(Format::from_path(Path::new("data/complex.JSON")), Some(Format::Json));
+        assert_eq!(Format::from_path(Path::new("rows.jsonl")), Some(Format::Jsonl));
+        assert_eq!(Format::from_path(Path::new("rows.ndjson")), Some(Format::Jsonl));
+        assert_eq!(Format::from_path(Path::new("rows.txt")), None);
+        assert_eq!(Format::from_path(Path::new("rows")), None);
+
+        assert!(matches!(Dataset::load("rows.txt", &["a"], &["y"]), Err(DatasetError::UnknownFormat(_))));
+    }
+
+    #[test]
+    fn booleans_are_read_as_one_and_zero()
+    {
+        let csv = "a,b,y\ntrue,false,true\n";
+        let json = r#"[{"a": true, "b": false, "y": "true"}]"#;
+
+        for dataset in [parse(csv, Format::Csv).unwrap(), parse(json, Format::Json).unwrap()]
+        {
+            assert_eq!(dataset.inputs, vec![vec![1.0, 0.0]]);
+            assert_eq!(dataset.outputs, vec![vec![1.0]]);
+        }
+    }
+
+    #[test]
+    fn errors_count_rows_from_one()
+    {
+        let missing = [
+            ("a,y\n1,2\n", Format::Csv, 1, "b"),
+            (r#"[{"a": 1, "b": 2, "y": 3}, {"a": 4, "y": 6}]"#, Format::Json, 2, "b"),
+            ("{\"a\": 1, \"b\": 2, \"y\": 3}\n\n{\"a\": 4, \"b\": 5}\n", Format::Jsonl, 2, "y")
+        ];
+        for (text, format, row, column) in missing
+        {
+            match parse(text, format)
+            {
+                Err(DatasetError::MissingColumn {row: actual_row, column: actual_column}) =>
+                {
+                    assert_eq!((actual_row, actual_column.as_str()), (row, column), "{:?}", format);
+                }
+                result => panic!("{:?}: {:?}", format, result.err())
+            }
+        }
+
+        let result = parse("a,b,y\n1,2,3\n4,five,6\n", Format::Csv);
+        assert!(matches!(result, Err(DatasetError::NotANumber {row: 2, ref column, ref value}) if column == "b" && value == "five"));
+
+        let result = parse(r#"[{"a": 1, "b": 2, "y": 3}, {"a": 4, "b": [5], "y": 6}]"#, Format::Json);
+        assert!(matches!(result, Err(DatasetError::NotANumber {row: 2, ref column, ref value}) if column == "b" && value == "[5]"));
+
+        let result = parse(r#"[{"a": 1, "b": 2, "y": 3}, 4]"#, Format::Json);
+        assert!(matches!(result, Err(DatasetError::NotAnObject {row: 2})));
+    }
+
+    #[test]
+    fn datasets_without_examples_are_rejected()
+    {
+        assert!(matches!(parse("a,b,y\n", Format::Csv), Err(DatasetError::Empty)));
+        assert!(matches!(parse("[]", Format::Json), Err(DatasetError::Empty)));
+        assert!(matches!(parse("\n  \n", Format::Jsonl), Err(DatasetError::Empty)));
+    }
+}
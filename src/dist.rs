@@ -1,211 +1,925 @@
-use std::f32::consts::TAU;
-use crate::rng::RNG;
-
-pub trait Distribution
-{
-    type SampleType;
-    type GradType;
-    fn sample(&self, rng: &mut RNG) -> Self::SampleType;
-    fn argmax(&self) -> Self::SampleType;
-    fn log_prob(&self, x: Self::SampleType) -> f32;
-    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType;
-    fn update(&mut self, grad: Self::GradType, rate: f32);
-}
-
-// Normal distribution
-pub struct Normal
-{
-    pub mean: f32,
-    pub stddev: f32
-}
-
-impl Normal
-{
-    pub fn new(mean: f32, stddev: f32) -> Self
-    {
-        Self {mean, stddev}
-    }
-}
-
-impl Distribution for Normal
-{
-    type SampleType = f32;
-    type GradType = f32;
-
-    fn sample(&self, rng: &mut RNG) -> Self::SampleType
-    {
-        let u1: f32 = rng.sample();
-        let u2: f32 = rng.sample();
-        let mag: f32 = self.stddev * (-2.0 * u1.ln()).sqrt();
-        mag * (TAU * u2).cos() + self.mean
-    }
-
-    fn argmax(&self) -> Self::SampleType
-    {
-        self.mean
-    }
-
-    fn log_prob(&self, x: Self::SampleType) -> f32
-    {
-        const HALF_LN_TAU: f32 = 0.91893853320467274178032973640562;
-        let z = (x - self.mean) / self.stddev;
-        -self.stddev.ln() - HALF_LN_TAU - 0.5 * z.powf(2.0)
-    }
-
-    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType
-    {
-        let mut grad_mu: f32 = 0.0;
-        
-        let num_mutations: f32 = traces.len() as f32;
-        for i in 0..traces.len()
-        {
-            let (sample, score) = traces[i];
-            let normalized = (sample - self.mean) / self.stddev;
-            grad_mu += normalized * score;
-        }
-
-        grad_mu /= self.stddev * num_mutations;
-        grad_mu
-    }
-
-    fn update(&mut self, grad: Self::GradType, rate: f32)
-    {
-        self.mean -= rate * self.stddev * grad;
-    }
-}
-
-// Categorical distribution
-pub struct Categorical
-{
-    pub vo: bool,
-    pub logits: Vec<f32>
-}
-
-impl Categorical
-{
-    pub fn new(vo: bool, logits: Vec<f32>) -> Self
-    {
-        Self {vo, logits}
-    }
-}
-
-impl Distribution for Categorical
-{
-    type SampleType = usize;
-    type GradType = Vec<f32>;
-
-    fn sample(&self, rng: &mut RNG) -> Self::SampleType
-    {
-        let mut score: f32 = self.logits[0];
-        let mut index: usize = 0;
-
-        for i in 1..self.logits.len()
-        {
-            let u: f32 = rng.sample();
-            let gumbel: f32 = -(-u.ln()).ln();
-            let sample: f32 = self.logits[i] + gumbel;
-            if sample > score
-            {
-                score = sample;
-                index = i;
-            }
-        }
-
-        index
-    }
-
-    fn argmax(&self) -> Self::SampleType
-    {
-        let mut score: f32 = self.logits[0];
-        let mut index: usize = 0;
-
-        for i in 1..self.logits.len()
-        {
-            let sample: f32 = self.logits[i];
-            if sample > score
-            {
-                score = sample;
-                index = i;
-            }
-        }
-
-        index
-    }
-
-    fn log_prob(&self, x: Self::SampleType) -> f32
-    {
-        let mut max_val: f32 = self.logits[0];
-        for i in 1..self.logits.len()
-        {
-            if self.logits[i] > max_val
-            {
-                max_val = self.logits[i];
-            }
-        }
-
-        let mut sum: f32 = 0.0;
-        for logit in self.logits.iter()
-        {
-            sum += (logit - max_val).exp();
-        }
-        
-        (self.logits[x as usize] - max_val) - sum.ln()
-    }
-
-    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType
-    {
-        let mut grad: Vec<f32> = vec![0.0; self.logits.len()];
-        let num_mutations: f32 = traces.len() as f32;
-        
-        for i in 0..traces.len()
-        {
-            let (sample, score) = traces[i];
-            let mut scored_grad: f32 = 0.0;
-            let prob = self.log_prob(sample).exp();
-        
-            for j in 0..self.logits.len()
-            {
-                let prob_j = self.log_prob(j).exp();
-                if j == sample
-                {
-                    
-                    scored_grad = (1.0 - prob) * score;
-                    if self.vo == true
-                    {
-                        scored_grad *= prob;
-                    }
-                }
-
-                else
-                {
-                    scored_grad = -prob_j * score;
-                    if self.vo == true
-                    {
-                        scored_grad *= prob;
-                    }
-                }
-                
-                grad[j] += scored_grad;
-            }
-        }
-
-        for i in 0..grad.len()
-        {
-            grad[i] /= num_mutations;
-            if !self.vo
-            {
-                grad[i] /= (self.log_prob(i) - 1.0).powf(2.);
-            }
-        }
-        
-        grad
-    }
-
-    fn update(&mut self, grad: Self::GradType, rate: f32)
-    {
-        for i in 0..self.logits.len()
-        {
-            self.logits[i] -= rate * grad[i];
-        }
-    }
-}
+use crate::estimator::GradientEstimator;
+use crate::linalg;
+use crate::linalg::Matrix;
+use crate::optim::Gradient;
+use crate::rng::RandomSource;
+use crate::sampling::normal_quantile;
+
+pub trait Distribution
+{
+    type SampleType;
+    type GradType: Gradient;
+    fn sample<R: RandomSource + ?Sized>(&self, rng: &mut R) -> Self::SampleType;
+    fn argmax(&self) -> Self::SampleType;
+    fn log_prob(&self, x: Self::SampleType) -> f32;
+    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType;
+    fn update(&mut self, grad: Self::GradType, rate: f32);
+    // Entropy in nats, differential for continuous distributions
+    fn entropy(&self) -> f32;
+    // KL(self || other) in nats
+    fn kl(&self, other: &Self) -> f32;
+    // Gradients of the entropy and of KL(self || prior), in the coordinates `grad` returns
+    fn entropy_grad(&self) -> Self::GradType;
+    fn kl_grad(&self, prior: &Self) -> Self::GradType;
+
+    // Applies whatever rescaling `grad` puts on its estimates to another gradient in the same
+    // coordinates. Plain gradients are left as they are.
+    fn precondition(&self, grad: Self::GradType) -> Self::GradType
+    {
+        grad
+    }
+
+    // Whether samples come from a finite set, only these entropies are comparable to zero
+    fn discrete(&self) -> bool
+    {
+        false
+    }
+
+    // Number of uniforms `quantile` maps to a sample, zero if it has no inverse CDF
+    fn quantile_dim(&self) -> usize
+    {
+        0
+    }
+
+    // Sample at the point u of the unit cube, used by the non-independent `Sampling` modes
+    fn quantile(&self, _u: &[f32]) -> Option<Self::SampleType>
+    {
+        None
+    }
+}
+
+const HALF_LN_TAU: f32 = 0.91893853320467274178032973640562;
+
+// Normal distribution
+#[derive(Clone)]
+pub struct Normal
+{
+    pub mean: f32,
+    pub stddev: f32,
+    pub learn_stddev: bool
+}
+
+// Gradient of the mean and of log(stddev)
+pub struct NormalGrad
+{
+    pub mean: f32,
+    pub log_stddev: f32
+}
+
+impl Gradient for NormalGrad
+{
+    fn flatten(&self) -> Vec<f32>
+    {
+        vec![self.mean, self.log_stddev]
+    }
+
+    fn unflatten(&self, values: &[f32]) -> Self
+    {
+        Self {mean: values[0], log_stddev: values[1]}
+    }
+}
+
+impl Normal
+{
+    // Keeps stddev fixed, only the mean is learned
+    pub fn new(mean: f32, stddev: f32) -> Self
+    {
+        Self {mean, stddev, learn_stddev: false}
+    }
+
+    // Learns both the mean and log(stddev), as in SNES
+    pub fn learnable(mean: f32, stddev: f32) -> Self
+    {
+        Self {mean, stddev, learn_stddev: true}
+    }
+}
+
+impl Distribution for Normal
+{
+    type SampleType = f32;
+    type GradType = NormalGrad;
+
+    fn sample<R: RandomSource + ?Sized>(&self, rng: &mut R) -> Self::SampleType
+    {
+        self.stddev * rng.standard_normal() + self.mean
+    }
+
+    fn argmax(&self) -> Self::SampleType
+    {
+        self.mean
+    }
+
+    fn log_prob(&self, x: Self::SampleType) -> f32
+    {
+        let z = (x - self.mean) / self.stddev;
+        -self.stddev.ln() - HALF_LN_TAU - 0.5 * z.powf(2.0)
+    }
+
+    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType
+    {
+        let mut grad_mu: f32 = 0.0;
+        let mut grad_sigma: f32 = 0.0;
+        
+        let num_mutations: f32 = traces.len() as f32;
+        for (sample, score) in traces
+        {
+            let normalized = (sample - self.mean) / self.stddev;
+            grad_mu += normalized * score;
+            grad_sigma += (normalized * normalized - 1.0) * score;
+        }
+
+        grad_mu /= self.stddev * num_mutations;
+        grad_sigma /= num_mutations;
+
+        if !self.learn_stddev
+        {
+            grad_sigma = 0.0;
+        }
+
+        NormalGrad {mean: grad_mu, log_stddev: grad_sigma}
+    }
+
+    // Natural gradient step, the Fisher of (mean, log stddev) is diag(1 / stddev^2, 2)
+    fn update(&mut self, grad: Self::GradType, rate: f32)
+    {
+        self.mean -= rate * self.stddev * self.stddev * grad.mean;
+        if self.learn_stddev
+        {
+            self.stddev *= (-0.5 * rate * grad.log_stddev).exp();
+        }
+    }
+
+    fn entropy(&self) -> f32
+    {
+        HALF_LN_TAU + 0.5 + self.stddev.ln()
+    }
+
+    fn kl(&self, other: &Self) -> f32
+    {
+        let ratio = self.stddev / other.stddev;
+        let z = (self.mean - other.mean) / other.stddev;
+        0.5 * (ratio * ratio + z * z - 1.0) - ratio.ln()
+    }
+
+    fn entropy_grad(&self) -> Self::GradType
+    {
+        NormalGrad {mean: 0.0, log_stddev: if self.learn_stddev {1.0} else {0.0}}
+    }
+
+    fn kl_grad(&self, prior: &Self) -> Self::GradType
+    {
+        let ratio = self.stddev / prior.stddev;
+        let log_stddev = if self.learn_stddev {ratio * ratio - 1.0} else {0.0};
+        NormalGrad {mean: (self.mean - prior.mean) / (prior.stddev * prior.stddev), log_stddev}
+    }
+
+    fn quantile_dim(&self) -> usize
+    {
+        1
+    }
+
+    fn quantile(&self, u: &[f32]) -> Option<Self::SampleType>
+    {
+        Some(self.stddev * normal_quantile(u[0] as f64) as f32 + self.mean)
+    }
+}
+
+// Multivariate normal distribution, x = mean + scale * z with z ~ N(0, I)
+#[derive(Clone)]
+pub enum Covariance
+{
+    // Diagonal scale, separable NES (SNES)
+    Separable,
+    // Full scale, exponential NES (xNES)
+    Full
+}
+
+#[derive(Clone)]
+pub struct MultivariateNormal
+{
+    pub mean: Vec<f32>,
+    pub scale: Matrix,
+    pub covariance: Covariance
+}
+
+// Gradient in the local coordinates z of the current search distribution
+pub struct MultivariateNormalGrad
+{
+    pub mean: Vec<f32>,
+    pub scale: Matrix
+}
+
+// Flattened as the mean followed by the rows of the scale
+impl Gradient for MultivariateNormalGrad
+{
+    fn flatten(&self) -> Vec<f32>
+    {
+        let mut values = self.mean.clone();
+        for row in self.scale.iter()
+        {
+            values.extend_from_slice(row);
+        }
+        values
+    }
+
+    fn unflatten(&self, values: &[f32]) -> Self
+    {
+        let dim = self.mean.len();
+        let mean = values[..dim].to_vec();
+        let scale = values[dim..].chunks(dim).map(|row| row.to_vec()).collect();
+        Self {mean, scale}
+    }
+}
+
+impl MultivariateNormal
+{
+    pub fn new(mean: Vec<f32>, stddev: f32, covariance: Covariance) -> Self
+    {
+        let mut scale = linalg::identity(mean.len());
+        for (i, row) in scale.iter_mut().enumerate()
+        {
+            row[i] = stddev;
+        }
+
+        Self {mean, scale, covariance}
+    }
+
+    pub fn dim(&self) -> usize
+    {
+        self.mean.len()
+    }
+
+    // B = prior.scale^-1 * scale, so that prior.covariance^-1 = B^-T B^-1 relative to this one
+    fn relative_scale(&self, prior: &Self) -> Matrix
+    {
+        assert_eq!(self.dim(), prior.dim(), "KL between normals of different dimensions");
+        let dim = self.dim();
+        let mut relative = vec![vec![0.0; dim]; dim];
+        for j in 0..dim
+        {
+            let column: Vec<f32> = self.scale.iter().map(|row| row[j]).collect();
+            for (i, b) in linalg::solve(&prior.scale, &column).into_iter().enumerate()
+            {
+                relative[i][j] = b;
+            }
+        }
+        relative
+    }
+
+    fn whiten(&self, x: &[f32]) -> Vec<f32>
+    {
+        let centered: Vec<f32> = x.iter().zip(self.mean.iter()).map(|(x, m)| x - m).collect();
+        match self.covariance
+        {
+            Covariance::Separable => centered.iter().enumerate().map(|(i, c)| c / self.scale[i][i]).collect(),
+            Covariance::Full => linalg::solve(&self.scale, &centered)
+        }
+    }
+}
+
+impl Distribution for MultivariateNormal
+{
+    type SampleType = Vec<f32>;
+    type GradType = MultivariateNormalGrad;
+
+    fn sample<R: RandomSource + ?Sized>(&self, rng: &mut R) -> Self::SampleType
+    {
+        let z: Vec<f32> = (0..self.dim()).map(|_| rng.standard_normal()).collect();
+        linalg::mat_vec(&self.scale, &z)
+            .iter()
+            .zip(self.mean.iter())
+            .map(|(x, m)| x + m)
+            .collect()
+    }
+
+    fn argmax(&self) -> Self::SampleType
+    {
+        self.mean.clone()
+    }
+
+    fn log_prob(&self, x: Self::SampleType) -> f32
+    {
+        let z = self.whiten(&x);
+        let sq: f32 = z.iter().map(|z| z * z).sum();
+        -linalg::log_abs_det(&self.scale) - HALF_LN_TAU * self.dim() as f32 - 0.5 * sq
+    }
+
+    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType
+    {
+        let dim = self.dim();
+        let mut grad_mean = vec![0.0; dim];
+        let mut grad_scale = vec![vec![0.0; dim]; dim];
+
+        let num_mutations: f32 = traces.len() as f32;
+        for (sample, score) in traces
+        {
+            let z = self.whiten(&sample);
+            for i in 0..dim
+            {
+                grad_mean[i] += z[i] * score;
+                match self.covariance
+                {
+                    Covariance::Separable => grad_scale[i][i] += (z[i] * z[i] - 1.0) * score,
+                    Covariance::Full =>
+                    {
+                        for j in 0..dim
+                        {
+                            let delta = if i == j {1.0} else {0.0};
+                            grad_scale[i][j] += (z[i] * z[j] - delta) * score;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (g, row) in grad_mean.iter_mut().zip(grad_scale.iter_mut())
+        {
+            *g /= num_mutations;
+            for x in row.iter_mut()
+            {
+                *x /= num_mutations;
+            }
+        }
+
+        MultivariateNormalGrad {mean: grad_mean, scale: grad_scale}
+    }
+
+    // Natural gradient step, mean -= rate * A * g_mean and A = A * expm(-rate / 2 * G_scale)
+    fn update(&mut self, grad: Self::GradType, rate: f32)
+    {
+        let step = linalg::mat_vec(&self.scale, &grad.mean);
+        for (m, s) in self.mean.iter_mut().zip(step.iter())
+        {
+            *m -= rate * s;
+        }
+
+        match self.covariance
+        {
+            Covariance::Separable =>
+            {
+                for (i, row) in self.scale.iter_mut().enumerate()
+                {
+                    row[i] *= (-0.5 * rate * grad.scale[i][i]).exp();
+                }
+            }
+            Covariance::Full =>
+            {
+                let exponent: Matrix = grad.scale.iter()
+                    .map(|row| row.iter().map(|g| -0.5 * rate * g).collect())
+                    .collect();
+                self.scale = linalg::mat_mul(&self.scale, &linalg::expm(&exponent));
+            }
+        }
+    }
+
+    fn entropy(&self) -> f32
+    {
+        (HALF_LN_TAU + 0.5) * self.dim() as f32 + linalg::log_abs_det(&self.scale)
+    }
+
+    fn kl(&self, other: &Self) -> f32
+    {
+        let relative = self.relative_scale(other);
+        let trace: f32 = relative.iter().flatten().map(|b| b * b).sum();
+        let diff: Vec<f32> = other.mean.iter().zip(self.mean.iter()).map(|(m2, m1)| m2 - m1).collect();
+        let mahalanobis: f32 = linalg::solve(&other.scale, &diff).iter().map(|c| c * c).sum();
+        let log_det = linalg::log_abs_det(&other.scale) - linalg::log_abs_det(&self.scale);
+        0.5 * (trace + mahalanobis - self.dim() as f32) + log_det
+    }
+
+    // The scale coordinates are L in scale * expm(L), which raises the entropy by trace(L)
+    fn entropy_grad(&self) -> Self::GradType
+    {
+        MultivariateNormalGrad {mean: vec![0.0; self.dim()], scale: linalg::identity(self.dim())}
+    }
+
+    // With B = prior.scale^-1 * scale and c = prior.scale^-1 * (mean - prior.mean), the
+    // gradient is B^T c for the local mean and B^T B - I for L
+    fn kl_grad(&self, prior: &Self) -> Self::GradType
+    {
+        let dim = self.dim();
+        let relative = self.relative_scale(prior);
+        let diff: Vec<f32> = self.mean.iter().zip(prior.mean.iter()).map(|(m1, m2)| m1 - m2).collect();
+        let c = linalg::solve(&prior.scale, &diff);
+
+        let mean = (0..dim).map(|j| (0..dim).map(|i| relative[i][j] * c[i]).sum()).collect();
+        let separable = matches!(self.covariance, Covariance::Separable);
+        let mut scale = vec![vec![0.0; dim]; dim];
+        for (i, row) in scale.iter_mut().enumerate()
+        {
+            for (j, x) in row.iter_mut().enumerate()
+            {
+                if separable && i != j
+                {
+                    continue;
+                }
+
+                let delta = if i == j {1.0} else {0.0};
+                *x = (0..dim).map(|k| relative[k][i] * relative[k][j]).sum::<f32>() - delta;
+            }
+        }
+
+        MultivariateNormalGrad {mean, scale}
+    }
+
+    fn quantile_dim(&self) -> usize
+    {
+        self.dim()
+    }
+
+    fn quantile(&self, u: &[f32]) -> Option<Self::SampleType>
+    {
+        let z: Vec<f32> = u.iter().map(|&u| normal_quantile(u as f64) as f32).collect();
+        Some(linalg::mat_vec(&self.scale, &z)
+            .iter()
+            .zip(self.mean.iter())
+            .map(|(x, m)| x + m)
+            .collect())
+    }
+}
+
+// Categorical distribution, the softmax of the logits is cached and refreshed on every update
+#[derive(Clone)]
+pub struct Categorical
+{
+    pub estimator: GradientEstimator,
+    logits: Vec<f32>,
+    log_probs: Vec<f32>,
+    probs: Vec<f32>
+}
+
+impl Categorical
+{
+    pub fn new(estimator: GradientEstimator, logits: Vec<f32>) -> Self
+    {
+        let mut dist = Self {estimator, logits, log_probs: Vec::new(), probs: Vec::new()};
+        dist.refresh();
+        dist
+    }
+
+    pub fn logits(&self) -> &[f32]
+    {
+        &self.logits
+    }
+
+    pub fn log_probs(&self) -> &[f32]
+    {
+        &self.log_probs
+    }
+
+    pub fn probs(&self) -> &[f32]
+    {
+        &self.probs
+    }
+
+    pub fn set_logits(&mut self, logits: Vec<f32>)
+    {
+        self.logits = logits;
+        self.refresh();
+    }
+
+    fn refresh(&mut self)
+    {
+        let max_val: f32 = self.logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+        let mut sum: f32 = 0.0;
+        for logit in self.logits.iter()
+        {
+            sum += (logit - max_val).exp();
+        }
+
+        let log_norm = max_val + sum.ln();
+        self.log_probs = self.logits.iter().map(|logit| logit - log_norm).collect();
+        self.probs = self.log_probs.iter().map(|lp| lp.exp()).collect();
+    }
+
+    // Categories with zero probability are never returned even when rounding leaves the
+    // cumulative sum short of 1. NaN probabilities would silently return 0 forever, so they
+    // fail here instead.
+    fn inverse_cdf(&self, u: f32) -> usize
+    {
+        assert!(self.probs.iter().all(|p| p.is_finite()), "categorical probabilities are not finite: logits {:?}", self.logits);
+
+        let mut cumulative: f32 = 0.0;
+        let mut last: usize = 0;
+
+        for (i, &p) in self.probs.iter().enumerate()
+        {
+            if p > 0.0
+            {
+                cumulative += p;
+                last = i;
+                if u < cumulative
+                {
+                    return i;
+                }
+            }
+        }
+
+        last
+    }
+}
+
+impl Distribution for Categorical
+{
+    type SampleType = usize;
+    type GradType = Vec<f32>;
+
+    fn sample<R: RandomSource + ?Sized>(&self, rng: &mut R) -> Self::SampleType
+    {
+        self.inverse_cdf(rng.uniform_open01())
+    }
+
+    fn argmax(&self) -> Self::SampleType
+    {
+        let mut score: f32 = self.logits[0];
+        let mut index: usize = 0;
+
+        for i in 1..self.logits.len()
+        {
+            let sample: f32 = self.logits[i];
+            if sample > score
+            {
+                score = sample;
+                index = i;
+            }
+        }
+
+        index
+    }
+
+    fn log_prob(&self, x: Self::SampleType) -> f32
+    {
+        self.log_probs[x]
+    }
+
+    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType
+    {
+        self.estimator.estimate(&self.log_probs, &traces)
+    }
+
+    fn update(&mut self, grad: Self::GradType, rate: f32)
+    {
+        for (logit, g) in self.logits.iter_mut().zip(grad)
+        {
+            *logit -= rate * g;
+        }
+        self.refresh();
+    }
+
+    fn entropy(&self) -> f32
+    {
+        self.probs.iter()
+            .zip(self.log_probs.iter())
+            .filter(|(&p, _)| p > 0.0)
+            .map(|(p, lp)| -p * lp)
+            .sum()
+    }
+
+    fn kl(&self, other: &Self) -> f32
+    {
+        self.probs.iter()
+            .zip(self.log_probs.iter().zip(other.log_probs.iter()))
+            .filter(|(&p, _)| p > 0.0)
+            .map(|(p, (lp, lq))| p * (lp - lq))
+            .sum()
+    }
+
+    // dH / dlogit_k = -p_k (log p_k + H)
+    fn entropy_grad(&self) -> Self::GradType
+    {
+        let entropy = self.entropy();
+        self.probs.iter()
+            .zip(self.log_probs.iter())
+            .map(|(&p, lp)| if p > 0.0 {-p * (lp + entropy)} else {0.0})
+            .collect()
+    }
+
+    // dKL / dlogit_k = p_k (log p_k - log q_k - KL)
+    fn kl_grad(&self, prior: &Self) -> Self::GradType
+    {
+        let kl = self.kl(prior);
+        self.probs.iter()
+            .zip(self.log_probs.iter().zip(prior.log_probs.iter()))
+            .map(|(&p, (lp, lq))| if p > 0.0 {p * (lp - lq - kl)} else {0.0})
+            .collect()
+    }
+
+    fn precondition(&self, grad: Self::GradType) -> Self::GradType
+    {
+        self.estimator.precondition(&self.log_probs, grad)
+    }
+
+    fn discrete(&self) -> bool
+    {
+        true
+    }
+
+    fn quantile_dim(&self) -> usize
+    {
+        1
+    }
+
+    fn quantile(&self, u: &[f32]) -> Option<Self::SampleType>
+    {
+        Some(self.inverse_cdf(u[0]))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::rng::RNG;
+    use crate::stats;
+
+    fn frequencies_match(logits: Vec<f32>, seed: u32)
+    {
+        const NUM_SAMPLES: usize = 20000;
+
+        let dist = Categorical::new(GradientEstimator::Dnes, logits.clone());
+        let mut rng = RNG::new(seed);
+        let mut observed = vec![0u64; logits.len()];
+        for _ in 0..NUM_SAMPLES
+        {
+            observed[dist.sample(&mut rng)] += 1;
+        }
+
+        let expected: Vec<f64> = (0..logits.len())
+            .map(|i| (dist.log_prob(i) as f64).exp() * NUM_SAMPLES as f64)
+            .collect();
+
+        let p_value = stats::chi_square_test(&observed, &expected);
+        assert!(p_value > 1e-4, "logits {:?}: p-value {} (observed {:?})", logits, p_value, observed);
+    }
+
+    #[test]
+    fn categorical_sample_matches_log_prob()
+    {
+        frequencies_match(vec![0.0; 2], 1);
+        frequencies_match(vec![0.0; 3], 2);
+        frequencies_match(vec![0.0; 17], 3);
+        frequencies_match(vec![1.0, 0.0], 4);
+        frequencies_match(vec![0.0, 0.0, 0.0, 3.0], 5);
+        frequencies_match(vec![-2.0, 0.5, 1.5, -0.3, 0.9], 6);
+        frequencies_match(vec![5.0, -5.0, 0.0], 7);
+        frequencies_match(vec![100.0, 101.0, 99.5], 8);
+        frequencies_match(vec![-100.0, -101.0, -99.5], 9);
+        frequencies_match(vec![0.0, -30.0, 0.0, -30.0], 10);
+    }
+
+    #[test]
+    fn categorical_sample_matches_log_prob_for_random_logits()
+    {
+        let mut logit_rng = RNG::new(42);
+        for k in 2..40
+        {
+            let logits: Vec<f32> = (0..k).map(|_| logit_rng.gen_range(-3.0..3.0)).collect();
+            frequencies_match(logits, 100 + k as u32);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not finite")]
+    fn categorical_sample_rejects_nan_probabilities()
+    {
+        let dist = Categorical::new(GradientEstimator::Dnes, vec![f32::NAN; 3]);
+        dist.sample(&mut RNG::new(0));
+    }
+
+    #[test]
+    fn categorical_sample_skips_impossible_categories()
+    {
+        let dist = Categorical::new(GradientEstimator::Dnes, vec![f32::NEG_INFINITY, 0.0, f32::NEG_INFINITY]);
+        let mut rng = RNG::new(0);
+        for _ in 0..10000
+        {
+            assert_eq!(dist.sample(&mut rng), 1);
+        }
+    }
+
+    fn assert_grads_close(actual: &[f32], expected: &[f32], what: &str)
+    {
+        assert_eq!(actual.len(), expected.len(), "{}", what);
+        for (i, (a, e)) in actual.iter().zip(expected.iter()).enumerate()
+        {
+            assert!((a - e).abs() < 1e-2 * e.abs().max(1.0), "{} {}: {:?} != {:?}", what, i, actual, expected);
+        }
+    }
+
+    // Central differences of `f` along every coordinate `step` moves the distribution in
+    fn finite_differences<D, S, F>(dist: &D, size: usize, step: S, f: F) -> Vec<f32>
+    where
+        S: Fn(&D, usize, f32) -> D,
+        F: Fn(&D) -> f32
+    {
+        const EPS: f32 = 1e-2;
+        (0..size).map(|i| (f(&step(dist, i, EPS)) - f(&step(dist, i, -EPS))) / (2.0 * EPS)).collect()
+    }
+
+    // Moves the mean, or log(stddev), by `eps`
+    fn step_normal(dist: &Normal, i: usize, eps: f32) -> Normal
+    {
+        let mut dist = dist.clone();
+        if i == 0
+        {
+            dist.mean += eps;
+        }
+        else
+        {
+            dist.stddev *= eps.exp();
+        }
+        dist
+    }
+
+    #[test]
+    fn normal_kl_matches_integration()
+    {
+        let pairs = [
+            (Normal::new(0.0, 1.0), Normal::new(0.0, 1.0)),
+            (Normal::new(1.0, 0.5), Normal::new(-0.5, 2.0)),
+            (Normal::new(-3.0, 2.0), Normal::new(0.0, 0.7))
+        ];
+
+        for (p, q) in pairs
+        {
+            // Trapezoid rule over 12 stddevs either side of the mean
+            const STEPS: usize = 20000;
+            let (lo, hi) = (p.mean - 12.0 * p.stddev, p.mean + 12.0 * p.stddev);
+            let width = (hi - lo) as f64 / STEPS as f64;
+            let integrand = |x: f64| {
+                let (lp, lq) = (p.log_prob(x as f32) as f64, q.log_prob(x as f32) as f64);
+                lp.exp() * (lp - lq)
+            };
+            let kl: f64 = (0..=STEPS)
+                .map(|k| {
+                    let weight = if k == 0 || k == STEPS {0.5} else {1.0};
+                    weight * integrand(lo as f64 + k as f64 * width)
+                })
+                .sum::<f64>() * width;
+
+            assert!((p.kl(&q) as f64 - kl).abs() < 1e-3, "KL {} != {}", p.kl(&q), kl);
+        }
+    }
+
+    #[test]
+    fn normal_gradients_match_finite_differences()
+    {
+        let dist = Normal::learnable(0.7, 1.3);
+        let prior = Normal::learnable(-0.4, 0.6);
+
+        let entropy = finite_differences(&dist, 2, step_normal, |d| d.entropy());
+        assert_grads_close(&dist.entropy_grad().flatten(), &entropy, "entropy");
+
+        let kl = finite_differences(&dist, 2, step_normal, |d| d.kl(&prior));
+        assert_grads_close(&dist.kl_grad(&prior).flatten(), &kl, "kl");
+
+        // A fixed stddev is not a coordinate
+        let fixed = Normal::new(0.7, 1.3);
+        assert_eq!(fixed.entropy_grad().log_stddev, 0.0);
+        assert_eq!(fixed.kl_grad(&prior).log_stddev, 0.0);
+        assert_eq!(fixed.kl_grad(&prior).mean, dist.kl_grad(&prior).mean);
+    }
+
+    fn multivariate(mean: Vec<f32>, scale: Matrix, covariance: Covariance) -> MultivariateNormal
+    {
+        let mut dist = MultivariateNormal::new(mean, 1.0, covariance);
+        dist.scale = scale;
+        dist
+    }
+
+    // Steps along one local coordinate, mean + scale * e_i or scale * expm(eps E_ij), through
+    // `update` with a one-hot gradient
+    fn step_multivariate(dist: &MultivariateNormal, i: usize, eps: f32) -> MultivariateNormal
+    {
+        let template = dist.entropy_grad();
+        let mut onehot = vec![0.0; template.flatten().len()];
+        onehot[i] = 1.0;
+        let rate = if i < dist.dim() {-eps} else {-2.0 * eps};
+
+        let mut dist = dist.clone();
+        dist.update(template.unflatten(&onehot), rate);
+        dist
+    }
+
+    fn multivariate_pairs() -> Vec<(MultivariateNormal, MultivariateNormal)>
+    {
+        let full = vec![vec![1.2, 0.3, 0.0], vec![-0.2, 0.8, 0.1], vec![0.4, 0.0, 1.5]];
+        let full_prior = vec![vec![0.9, 0.0, 0.2], vec![0.1, 1.4, 0.0], vec![0.0, -0.3, 0.7]];
+        let diagonal = vec![vec![1.2, 0.0, 0.0], vec![0.0, 0.5, 0.0], vec![0.0, 0.0, 2.0]];
+        let diagonal_prior = vec![vec![0.8, 0.0, 0.0], vec![0.0, 1.0, 0.0], vec![0.0, 0.0, 1.3]];
+
+        vec![
+            (
+                multivariate(vec![0.5, -1.0, 2.0], full, Covariance::Full),
+                multivariate(vec![0.0, 0.3, 1.0], full_prior, Covariance::Full)
+            ),
+            (
+                multivariate(vec![0.5, -1.0, 2.0], diagonal, Covariance::Separable),
+                multivariate(vec![0.0, 0.3, 1.0], diagonal_prior, Covariance::Separable)
+            )
+        ]
+    }
+
+    #[test]
+    fn multivariate_normal_gradients_match_finite_differences()
+    {
+        for (dist, prior) in multivariate_pairs()
+        {
+            let size = dist.entropy_grad().flatten().len();
+
+            let entropy = finite_differences(&dist, size, step_multivariate, |d| d.entropy());
+            assert_grads_close(&dist.entropy_grad().flatten(), &entropy, "entropy");
+
+            let kl = finite_differences(&dist, size, step_multivariate, |d| d.kl(&prior));
+            assert_grads_close(&dist.kl_grad(&prior).flatten(), &kl, "kl");
+        }
+    }
+
+    // 0.5 (tr(S_q^-1 S_p) + d^T S_q^-1 d - k + ln(det S_q / det S_p)) with S = scale scale^T,
+    // formed explicitly instead of through the scales
+    fn closed_form_kl(p: &MultivariateNormal, q: &MultivariateNormal) -> f32
+    {
+        let covariance = |dist: &MultivariateNormal| {
+            let transposed: Matrix = (0..dist.dim()).map(|j| dist.scale.iter().map(|row| row[j]).collect()).collect();
+            linalg::mat_mul(&dist.scale, &transposed)
+        };
+        let (cov_p, cov_q) = (covariance(p), covariance(q));
+
+        let trace: f32 = (0..p.dim())
+            .map(|j| {
+                let column: Vec<f32> = cov_p.iter().map(|row| row[j]).collect();
+                linalg::solve(&cov_q, &column)[j]
+            })
+            .sum();
+        let diff: Vec<f32> = q.mean.iter().zip(p.mean.iter()).map(|(a, b)| a - b).collect();
+        let mahalanobis: f32 = linalg::solve(&cov_q, &diff).iter().zip(diff.iter()).map(|(a, b)| a * b).sum();
+        let log_det = linalg::log_abs_det(&cov_q) - linalg::log_abs_det(&cov_p);
+
+        0.5 * (trace + mahalanobis - p.dim() as f32 + log_det)
+    }
+
+    #[test]
+    fn multivariate_normal_kl_matches_closed_form()
+    {
+        for (p, q) in multivariate_pairs()
+        {
+            for (a, b) in [(&p, &q), (&q, &p)]
+            {
+                let (kl, expected) = (a.kl(b), closed_form_kl(a, b));
+                assert!((kl - expected).abs() < 1e-4 * expected.max(1.0), "KL {} != {}", kl, expected);
+            }
+            assert!(p.kl(&p).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn multivariate_normal_log_prob_is_consistent_with_entropy()
+    {
+        // The entropy is the expected negative log density
+        for (dist, _) in multivariate_pairs()
+        {
+            let mut rng = RNG::new(11);
+            const NUM_SAMPLES: usize = 20000;
+            let mean_nll = (0..NUM_SAMPLES).map(|_| -dist.log_prob(dist.sample(&mut rng)) as f64).sum::<f64>() / NUM_SAMPLES as f64;
+            assert!((mean_nll - dist.entropy() as f64).abs() < 0.05, "{} != {}", mean_nll, dist.entropy());
+        }
+    }
+
+    // f(x) = x^T R^T diag(1, 10, 100) R x for a rotation R that mixes all three axes, so a
+    // separable search has to learn scales and a full one also the rotation
+    fn rotated_quadratic(x: &[f32]) -> f32
+    {
+        let rotation = linalg::expm(&vec![vec![0.0, -0.6, 0.3], vec![0.6, 0.0, -0.8], vec![-0.3, 0.8, 0.0]]);
+        linalg::mat_vec(&rotation, x).iter()
+            .zip([1.0, 10.0, 100.0])
+            .map(|(y, c)| c * y * y)
+            .sum()
+    }
+
+    #[test]
+    fn multivariate_normal_updates_shrink_a_quadratic()
+    {
+        use crate::shaping::{FitnessShaper, RankUtilities};
+
+        // A separable search cannot follow the rotation and only gets part of the way
+        for (name, covariance, shrink) in [("separable", Covariance::Separable, 0.1), ("full", Covariance::Full, 1e-5)]
+        {
+            let mut dist = MultivariateNormal::new(vec![1.0, -1.0, 1.0], 1.0, covariance);
+            let mut rng = RNG::new(5);
+            let start = rotated_quadratic(&dist.mean);
+
+            for _ in 0..1000
+            {
+                let samples: Vec<Vec<f32>> = (0..12).map(|_| dist.sample(&mut rng)).collect();
+                let losses: Vec<f32> = samples.iter().map(|x| rotated_quadratic(x)).collect();
+                let scores = RankUtilities.shape(&losses);
+                dist.update(dist.grad(samples.into_iter().zip(scores).collect()), 0.3);
+            }
+
+            let end = rotated_quadratic(&dist.mean);
+            assert!(end < shrink * start, "{}: loss {} -> {}", name, start, end);
+            assert!(dist.entropy() < MultivariateNormal::new(vec![0.0; 3], 1.0, Covariance::Full).entropy());
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// Gradient estimators for softmax-parameterised discrete distributions. Every estimator
+// turns (sample, score) traces into a descent direction for the logits, given the current
+// log-probabilities of each category.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradientEstimator
+{
+    // Plain score function (REINFORCE)
+    ScoreFunction,
+    // Score function weighted by the probability of the sample (variational optimisation)
+    VariationalOptimization,
+    // Score function preconditioned by (log p - 1)^2 (discrete NES)
+    Dnes,
+    // Exact natural gradient through the pseudo-inverse of the softmax Fisher
+    Natural,
+    // Score function with each score baselined by the mean of the others
+    LeaveOneOut
+}
+
+const MIN_NATURAL_PROB: f32 = 1e-6;
+
+impl GradientEstimator
+{
+    pub fn estimate(&self, log_probs: &[f32], traces: &[(usize, f32)]) -> Vec<f32>
+    {
+        let probs: Vec<f32> = log_probs.iter().map(|lp| lp.exp()).collect();
+        let num_mutations: f32 = traces.len() as f32;
+
+        let mut grad = match self
+        {
+            GradientEstimator::VariationalOptimization =>
+            {
+                score_function(&probs, traces.iter().map(|&(sample, score)| (sample, score, probs[sample])))
+            }
+            // With a single trace there are no others to baseline by
+            GradientEstimator::LeaveOneOut if traces.len() > 1 =>
+            {
+                let total: f32 = traces.iter().map(|&(_, score)| score).sum();
+                score_function(&probs, traces.iter().map(|&(sample, score)| {
+                    let baseline = (total - score) / (num_mutations - 1.0);
+                    (sample, score - baseline, 1.0)
+                }))
+            }
+            _ => score_function(&probs, traces.iter().map(|&(sample, score)| (sample, score, 1.0)))
+        };
+
+        for g in grad.iter_mut()
+        {
+            *g /= num_mutations;
+        }
+
+        self.precondition(log_probs, grad)
+    }
+
+    // Rescales a logit gradient the way `estimate` rescales its own, so that terms added to an
+    // estimate, like the entropy and KL penalties, keep their weight whatever the estimator
+    pub fn precondition(&self, log_probs: &[f32], mut grad: Vec<f32>) -> Vec<f32>
+    {
+        match self
+        {
+            GradientEstimator::Dnes =>
+            {
+                for (g, lp) in grad.iter_mut().zip(log_probs.iter())
+                {
+                    *g /= (lp - 1.0).powf(2.);
+                }
+            }
+            // The softmax Fisher diag(p) - pp^T is singular along the all-ones direction, but
+            // g / p solves F x = g since g sums to zero, so the pseudo-inverse solution is g / p
+            // projected onto the complement of that direction. p is floored so categories whose
+            // probability has underflowed get a zero step instead of 0 / 0.
+            GradientEstimator::Natural =>
+            {
+                for (g, lp) in grad.iter_mut().zip(log_probs.iter())
+                {
+                    *g /= lp.exp().max(MIN_NATURAL_PROB);
+                }
+
+                let mean: f32 = grad.iter().sum::<f32>() / grad.len() as f32;
+                for g in grad.iter_mut()
+                {
+                    *g -= mean;
+                }
+            }
+            _ => {}
+        }
+
+        grad
+    }
+}
+
+// Sum of weight * score * d log p(sample) / d logits over (sample, score, weight) traces.
+// d log p(x) / d logits = onehot(x) - p, so the probabilities only need to be subtracted
+// once, scaled by the total weighted score, which keeps this O(N + K)
+fn score_function<I>(probs: &[f32], traces: I) -> Vec<f32>
+where
+    I: Iterator<Item = (usize, f32, f32)>
+{
+    let mut grad: Vec<f32> = vec![0.0; probs.len()];
+    let mut total: f32 = 0.0;
+
+    for (sample, score, weight) in traces
+    {
+        grad[sample] += score * weight;
+        total += score * weight;
+    }
+
+    for (g, p) in grad.iter_mut().zip(probs.iter())
+    {
+        *g -= p * total;
+    }
+
+    grad
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::dist::{Categorical, Distribution};
+    use crate::rng::RNG;
+    use crate::shaping::{FitnessShaper, ZScore};
+
+    const ESTIMATORS: [GradientEstimator; 5] = [
+        GradientEstimator::ScoreFunction,
+        GradientEstimator::VariationalOptimization,
+        GradientEstimator::Dnes,
+        GradientEstimator::Natural,
+        GradientEstimator::LeaveOneOut
+    ];
+
+    // Long enough for the losing categories to stop being sampled and their probabilities
+    // to underflow
+    #[test]
+    fn logits_stay_finite_over_long_runs()
+    {
+        for estimator in ESTIMATORS
+        {
+            for seed in [1, 2, 10]
+            {
+                let mut dist = Categorical::new(estimator, vec![0.0; 4]);
+                let mut rng = RNG::new(seed);
+                for _ in 0..10000
+                {
+                    let samples: Vec<usize> = (0..50).map(|_| dist.sample(&mut rng)).collect();
+                    let losses: Vec<f32> = samples.iter().map(|&x| if x == 2 {0.0} else {10.0 + x as f32}).collect();
+                    let scores = ZScore::default().shape(&losses);
+                    dist.update(dist.grad(samples.into_iter().zip(scores).collect()), 0.1);
+                }
+
+                assert!(dist.logits().iter().all(|l| !l.is_nan()), "{:?}, seed {}: logits {:?}", estimator, seed, dist.logits());
+                assert_eq!(dist.argmax(), 2, "{:?}, seed {}: logits {:?}", estimator, seed, dist.logits());
+            }
+        }
+    }
+
+    #[test]
+    fn leave_one_out_falls_back_to_no_baseline_for_one_trace()
+    {
+        let log_probs = [0.5f32.ln(), 0.25f32.ln(), 0.25f32.ln()];
+        let traces = [(1, 2.0)];
+        let grad = GradientEstimator::LeaveOneOut.estimate(&log_probs, &traces);
+        assert_eq!(grad, GradientEstimator::ScoreFunction.estimate(&log_probs, &traces));
+        assert!(grad.iter().all(|g| g.is_finite()), "{:?}", grad);
+    }
+
+    // Only the rescaling differs between plain REINFORCE and the preconditioned estimators
+    #[test]
+    fn estimates_are_preconditioned_score_functions()
+    {
+        let log_probs = [0.6f32.ln(), 0.3f32.ln(), 0.1f32.ln()];
+        let traces = [(0, 1.5), (2, -0.5), (1, 0.25), (0, -1.0)];
+        let plain = GradientEstimator::ScoreFunction.estimate(&log_probs, &traces);
+
+        for estimator in [GradientEstimator::ScoreFunction, GradientEstimator::Dnes, GradientEstimator::Natural]
+        {
+            let expected = estimator.precondition(&log_probs, plain.clone());
+            assert_eq!(estimator.estimate(&log_probs, &traces), expected, "{:?}", estimator);
+        }
+        assert_ne!(GradientEstimator::Dnes.precondition(&log_probs, plain.clone()), plain);
+    }
+}
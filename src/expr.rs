@@ -0,0 +1,215 @@
+// Concrete programs, i.e. sketches with every hole filled in. A program is scored and printed
+// from the same tree, so what gets reported is always what was evaluated.
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp
+{
+    Add,
+    Sub,
+    Mul,
+    Div
+}
+
+impl BinOp
+{
+    pub fn apply(self, a: f32, b: f32) -> f32
+    {
+        match self
+        {
+            BinOp::Add => a + b,
+            BinOp::Sub => a - b,
+            BinOp::Mul => a * b,
+            BinOp::Div => a / b
+        }
+    }
+
+    pub fn symbol(self) -> &'static str
+    {
+        match self
+        {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/"
+        }
+    }
+
+    fn precedence(self) -> u8
+    {
+        match self
+        {
+            BinOp::Add | BinOp::Sub => SUM,
+            BinOp::Mul | BinOp::Div => PRODUCT
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp
+{
+    Gt,
+    Lt,
+    Eq,
+    Ge,
+    Le,
+    Ne
+}
+
+impl CmpOp
+{
+    pub fn apply(self, a: f32, b: f32) -> bool
+    {
+        match self
+        {
+            CmpOp::Gt => a > b,
+            CmpOp::Lt => a < b,
+            CmpOp::Eq => a == b,
+            CmpOp::Ge => a >= b,
+            CmpOp::Le => a <= b,
+            CmpOp::Ne => a != b
+        }
+    }
+
+    pub fn symbol(self) -> &'static str
+    {
+        match self
+        {
+            CmpOp::Gt => ">",
+            CmpOp::Lt => "<",
+            CmpOp::Eq => "==",
+            CmpOp::Ge => ">=",
+            CmpOp::Le => "<=",
+            CmpOp::Ne => "!="
+        }
+    }
+}
+
+// Variables index the program's variable list
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr
+{
+    Num(f32),
+    Var(usize),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    // if lhs op rhs { then } else { otherwise }
+    If(CmpOp, Box<Expr>, Box<Expr>, Box<Expr>, Box<Expr>)
+}
+
+// Binding strength of each syntactic level, loosest first
+const IF: u8 = 0;
+const SUM: u8 = 1;
+const PRODUCT: u8 = 2;
+const UNARY: u8 = 3;
+const ATOM: u8 = 4;
+
+impl Expr
+{
+    pub fn eval(&self, inputs: &[f32]) -> f32
+    {
+        match self
+        {
+            Expr::Num(value) => *value,
+            Expr::Var(index) => inputs[*index],
+            Expr::Neg(operand) => -operand.eval(inputs),
+            Expr::Binary(op, lhs, rhs) => op.apply(lhs.eval(inputs), rhs.eval(inputs)),
+            Expr::If(op, lhs, rhs, then, otherwise) =>
+            {
+                if op.apply(lhs.eval(inputs), rhs.eval(inputs))
+                {
+                    then.eval(inputs)
+                }
+                else
+                {
+                    otherwise.eval(inputs)
+                }
+            }
+        }
+    }
+
+    fn precedence(&self) -> u8
+    {
+        match self
+        {
+            Expr::Num(value) if *value < 0.0 => UNARY,
+            Expr::Num(_) | Expr::Var(_) => ATOM,
+            Expr::Neg(_) => UNARY,
+            Expr::Binary(op, _, _) => op.precedence(),
+            Expr::If(..) => IF
+        }
+    }
+
+    // Writes the expression in sketch syntax, parenthesised only where binding `min` or
+    // tighter requires it
+    fn write(&self, variables: &[String], min: u8, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        if self.precedence() < min
+        {
+            write!(f, "(")?;
+            self.write(variables, IF, f)?;
+            return write!(f, ")");
+        }
+
+        match self
+        {
+            Expr::Num(value) => write!(f, "{}", value),
+            Expr::Var(index) => write!(f, "{}", variables[*index]),
+            Expr::Neg(operand) =>
+            {
+                write!(f, "-")?;
+                operand.write(variables, UNARY, f)
+            }
+            Expr::Binary(op, lhs, rhs) =>
+            {
+                lhs.write(variables, op.precedence(), f)?;
+                write!(f, " {} ", op.symbol())?;
+                rhs.write(variables, op.precedence() + 1, f)
+            }
+            Expr::If(op, lhs, rhs, then, otherwise) =>
+            {
+                write!(f, "if ")?;
+                lhs.write(variables, SUM, f)?;
+                write!(f, " {} ", op.symbol())?;
+                rhs.write(variables, SUM, f)?;
+                write!(f, " {{ ")?;
+                then.write(variables, IF, f)?;
+                write!(f, " }} else ")?;
+
+                if let Expr::If(..) = **otherwise
+                {
+                    return otherwise.write(variables, IF, f);
+                }
+
+                write!(f, "{{ ")?;
+                otherwise.write(variables, IF, f)?;
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+// An expression together with the names of its inputs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program
+{
+    pub expr: Expr,
+    pub variables: Vec<String>
+}
+
+impl Program
+{
+    // `inputs` are ordered like `variables`
+    pub fn eval(&self, inputs: &[f32]) -> f32
+    {
+        self.expr.eval(inputs)
+    }
+}
+
+impl fmt::Display for Program
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        self.expr.write(&self.variables, IF, f)
+    }
+}
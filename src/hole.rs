@@ -0,0 +1,413 @@
+use std::any::Any;
+use std::marker::PhantomData;
+use std::ops::Index;
+use std::sync::Arc;
+
+use crate::dist::Distribution;
+use crate::optim::{Gradient, Optimizer, Sgd};
+use crate::rng::RandomSource;
+use crate::sampling::Sampling;
+
+// A hole's sample, type-erased but still comparable with other samples of the same hole
+trait HoleValue: Any + Send + Sync
+{
+    fn as_any(&self) -> &dyn Any;
+    fn eq_value(&self, other: &dyn HoleValue) -> bool;
+}
+
+impl<T: PartialEq + Send + Sync + 'static> HoleValue for T
+{
+    fn as_any(&self) -> &dyn Any
+    {
+        self
+    }
+
+    fn eq_value(&self, other: &dyn HoleValue) -> bool
+    {
+        other.as_any().downcast_ref::<T>().is_some_and(|other| self == other)
+    }
+}
+
+type Value = Arc<dyn HoleValue>;
+
+// Typed handle to a hole, returned by `HoleSet::add`
+pub struct HoleId<T>
+{
+    index: usize,
+    marker: PhantomData<fn() -> T>
+}
+
+impl<T> HoleId<T>
+{
+    pub fn index(&self) -> usize
+    {
+        self.index
+    }
+}
+
+impl<T> Clone for HoleId<T>
+{
+    fn clone(&self) -> Self
+    {
+        *self
+    }
+}
+
+impl<T> Copy for HoleId<T> {}
+
+// One joint sample of every hole in a `HoleSet`
+#[derive(Clone)]
+pub struct Assignment
+{
+    values: Vec<Value>
+}
+
+impl Assignment
+{
+    pub fn get<T: 'static>(&self, id: HoleId<T>) -> &T
+    {
+        self.value(id.index)
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.values.is_empty()
+    }
+
+    fn value<T: 'static>(&self, index: usize) -> &T
+    {
+        self.values[index]
+            .as_any()
+            .downcast_ref()
+            .expect("hole id does not belong to this assignment")
+    }
+}
+
+// Equal when every hole took the same value
+impl PartialEq for Assignment
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.values.len() == other.values.len()
+            && self.values.iter().zip(other.values.iter()).all(|(a, b)| a.eq_value(b.as_ref()))
+    }
+}
+
+impl<T: 'static> Index<HoleId<T>> for Assignment
+{
+    type Output = T;
+
+    fn index(&self, id: HoleId<T>) -> &T
+    {
+        self.get(id)
+    }
+}
+
+// A distribution together with what it was added as and the prior it is pulled toward
+struct Hole<D>
+{
+    dist: D,
+    initial: D,
+    prior: Option<(D, f32)>
+}
+
+// Object-safe view of a `Hole` whose samples are stored type-erased
+trait ErasedHole
+{
+    fn sample(&self, rng: &mut dyn RandomSource) -> Value;
+    fn argmax(&self) -> Value;
+    fn quantile_dim(&self) -> usize;
+    fn quantile(&self, u: &[f32], rng: &mut dyn RandomSource) -> Value;
+    fn entropy(&self) -> f32;
+    fn discrete(&self) -> bool;
+    fn reset(&mut self);
+    fn set_prior(&mut self, prior: Box<dyn Any>, weight: f32) -> bool;
+    fn step(&mut self, index: usize, population: &[Assignment], scores: &[f32], rate: f32, optimizer: &mut dyn Optimizer, entropy_bonus: f32);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<D> ErasedHole for Hole<D>
+where
+    D: Distribution + Clone + 'static,
+    D::SampleType: Clone + PartialEq + Send + Sync + 'static
+{
+    fn sample(&self, rng: &mut dyn RandomSource) -> Value
+    {
+        Arc::new(self.dist.sample(rng))
+    }
+
+    fn argmax(&self) -> Value
+    {
+        Arc::new(self.dist.argmax())
+    }
+
+    fn quantile_dim(&self) -> usize
+    {
+        self.dist.quantile_dim()
+    }
+
+    // Falls back to an independent draw when the distribution has no inverse CDF
+    fn quantile(&self, u: &[f32], rng: &mut dyn RandomSource) -> Value
+    {
+        match self.dist.quantile(u)
+        {
+            Some(x) => Arc::new(x),
+            None => Arc::new(self.dist.sample(rng))
+        }
+    }
+
+    fn entropy(&self) -> f32
+    {
+        self.dist.entropy()
+    }
+
+    fn discrete(&self) -> bool
+    {
+        self.dist.discrete()
+    }
+
+    fn reset(&mut self)
+    {
+        self.dist = self.initial.clone();
+    }
+
+    fn set_prior(&mut self, prior: Box<dyn Any>, weight: f32) -> bool
+    {
+        match prior.downcast::<D>()
+        {
+            Ok(prior) =>
+            {
+                self.prior = Some((*prior, weight));
+                true
+            }
+            Err(_) => false
+        }
+    }
+
+    // The penalties are preconditioned like the estimator's gradient and added to it before
+    // the optimizer sees it, so their weights are relative to the shaped scores whatever the
+    // estimator
+    fn step(&mut self, index: usize, population: &[Assignment], scores: &[f32], rate: f32, optimizer: &mut dyn Optimizer, entropy_bonus: f32)
+    {
+        let traces = population.iter()
+            .zip(scores.iter())
+            .map(|(props, &score)| (props.value::<D::SampleType>(index).clone(), score))
+            .collect();
+        let grad = self.dist.grad(traces);
+        let mut flat = grad.flatten();
+        let mut penalty = vec![0.0; flat.len()];
+        let mut penalised = false;
+
+        if entropy_bonus != 0.0 && self.dist.discrete()
+        {
+            for (p, e) in penalty.iter_mut().zip(self.dist.entropy_grad().flatten())
+            {
+                *p -= entropy_bonus * e;
+            }
+            penalised = true;
+        }
+
+        if let Some((prior, weight)) = &self.prior
+        {
+            for (p, k) in penalty.iter_mut().zip(self.dist.kl_grad(prior).flatten())
+            {
+                *p += weight * k;
+            }
+            penalised = true;
+        }
+
+        if penalised
+        {
+            let penalty = self.dist.precondition(grad.unflatten(&penalty)).flatten();
+            for (g, p) in flat.iter_mut().zip(penalty)
+            {
+                *g += p;
+            }
+        }
+
+        let direction = optimizer.step(index, &flat);
+        self.dist.update(grad.unflatten(&direction), rate);
+    }
+
+    fn as_any(&self) -> &dyn Any
+    {
+        &self.dist
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any
+    {
+        &mut self.dist
+    }
+}
+
+// Any mix of distributions, sampled and updated together. A copy of every hole as it was
+// added is kept so the whole set can be reset.
+#[derive(Default)]
+pub struct HoleSet
+{
+    holes: Vec<Box<dyn ErasedHole>>
+}
+
+impl HoleSet
+{
+    pub fn new() -> Self
+    {
+        Self {holes: Vec::new()}
+    }
+
+    pub fn add<D>(&mut self, dist: D) -> HoleId<D::SampleType>
+    where
+        D: Distribution + Clone + 'static,
+        D::SampleType: Clone + PartialEq + Send + Sync + 'static
+    {
+        self.holes.push(Box::new(Hole {initial: dist.clone(), dist, prior: None}));
+        HoleId {index: self.holes.len() - 1, marker: PhantomData}
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.holes.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.holes.is_empty()
+    }
+
+    // Puts every hole back to the distribution it was added with
+    pub fn reset(&mut self)
+    {
+        for hole in self.holes.iter_mut()
+        {
+            hole.reset();
+        }
+    }
+
+    // Adds weight * KL(hole || prior) to the hole's loss from now on
+    pub fn set_prior<D: Distribution + 'static>(&mut self, id: HoleId<D::SampleType>, prior: D, weight: f32)
+    {
+        let accepted = self.holes[id.index].set_prior(Box::new(prior), weight);
+        assert!(accepted, "prior is not of the hole's distribution type");
+    }
+
+    // Entropy of every discrete hole, in the order they were added
+    pub fn discrete_entropies(&self) -> Vec<f32>
+    {
+        self.holes.iter()
+            .filter(|hole| hole.discrete())
+            .map(|hole| hole.entropy())
+            .collect()
+    }
+
+    // The distribution behind a hole, if it is a `D`
+    pub fn dist<D: Distribution + 'static>(&self, id: HoleId<D::SampleType>) -> Option<&D>
+    {
+        self.holes[id.index].as_any().downcast_ref()
+    }
+
+    pub fn dist_mut<D: Distribution + 'static>(&mut self, id: HoleId<D::SampleType>) -> Option<&mut D>
+    {
+        self.holes[id.index].as_any_mut().downcast_mut()
+    }
+
+    pub fn sample<R: RandomSource>(&self, rng: &mut R) -> Assignment
+    {
+        Assignment {values: self.holes.iter().map(|hole| hole.sample(rng)).collect()}
+    }
+
+    // `n` joint samples drawn according to `sampling`, every hole takes its own block of
+    // coordinates of each point
+    pub fn sample_population<R: RandomSource>(&self, n: usize, sampling: Sampling, rng: &mut R) -> Vec<Assignment>
+    {
+        if sampling == Sampling::Independent
+        {
+            return (0..n).map(|_| self.sample(rng)).collect();
+        }
+
+        let dim = self.holes.iter().map(|hole| hole.quantile_dim()).sum();
+        let points = sampling.points(n, dim, rng);
+
+        let mut population = Vec::with_capacity(n);
+        for u in points
+        {
+            let mut values = Vec::with_capacity(self.holes.len());
+            let mut offset = 0;
+            for hole in self.holes.iter()
+            {
+                let hole_dim = hole.quantile_dim();
+                values.push(hole.quantile(&u[offset..offset + hole_dim], rng));
+                offset += hole_dim;
+            }
+            population.push(Assignment {values});
+        }
+
+        population
+    }
+
+    pub fn argmax(&self) -> Assignment
+    {
+        Assignment {values: self.holes.iter().map(|hole| hole.argmax()).collect()}
+    }
+
+    // Routes each hole's (sample, score) traces to its own `grad` and applies `update`
+    pub fn step(&mut self, population: &[Assignment], scores: &[f32], rate: f32)
+    {
+        self.step_with(population, scores, rate, &mut Sgd, 0.0);
+    }
+
+    // Same as `step` with every gradient passed through `optimizer` first, hole i uses slot i.
+    // `entropy_bonus` rewards the entropy of every discrete hole.
+    pub fn step_with(&mut self, population: &[Assignment], scores: &[f32], rate: f32, optimizer: &mut dyn Optimizer, entropy_bonus: f32)
+    {
+        for (index, hole) in self.holes.iter_mut().enumerate()
+        {
+            hole.step(index, population, scores, rate, optimizer, entropy_bonus);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::dist::Categorical;
+    use crate::estimator::GradientEstimator;
+
+    // With zero scores the estimator contributes nothing, so a step moves the logits by the
+    // preconditioned penalties alone
+    #[test]
+    fn penalties_are_preconditioned_like_the_estimate()
+    {
+        for estimator in [GradientEstimator::ScoreFunction, GradientEstimator::Dnes, GradientEstimator::Natural]
+        {
+            let dist = Categorical::new(estimator, vec![2.0, 0.0, -3.0]);
+            let prior = Categorical::new(estimator, vec![0.0; 3]);
+            let (bonus, weight, rate) = (0.3, 0.5, 0.1);
+
+            let mut holes = HoleSet::new();
+            let id = holes.add(dist.clone());
+            holes.set_prior(id, prior.clone(), weight);
+            let population = vec![holes.argmax(); 4];
+            holes.step_with(&population, &[0.0; 4], rate, &mut Sgd, bonus);
+
+            let penalty: Vec<f32> = dist.kl_grad(&prior).iter()
+                .zip(dist.entropy_grad())
+                .map(|(k, e)| weight * k - bonus * e)
+                .collect();
+            let mut expected = dist.clone();
+            expected.update(estimator.precondition(dist.log_probs(), penalty), rate);
+
+            let actual: &Categorical = holes.dist(id).unwrap();
+            for (a, e) in actual.logits().iter().zip(expected.logits())
+            {
+                assert!((a - e).abs() < 1e-6, "{:?}: logits {:?}, expected {:?}", estimator, actual.logits(), expected.logits());
+            }
+        }
+    }
+}
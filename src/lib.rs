@@ -0,0 +1,20 @@
+pub mod rng;
+pub mod dist;
+pub mod estimator;
+pub mod linalg;
+pub mod hole;
+pub mod train;
+pub mod sampling;
+pub mod shaping;
+pub mod optim;
+pub mod schedule;
+pub mod restart;
+pub mod stop;
+pub mod expr;
+pub mod sketch;
+pub mod pcfg;
+pub mod loss;
+pub mod dataset;
+
+#[cfg(test)]
+mod stats;
@@ -0,0 +1,258 @@
+// Small dense matrix helpers for the multivariate distributions, matrices are row-major
+
+pub type Matrix = Vec<Vec<f32>>;
+
+pub fn identity(dim: usize) -> Matrix
+{
+    let mut m = vec![vec![0.0; dim]; dim];
+    for (i, row) in m.iter_mut().enumerate()
+    {
+        row[i] = 1.0;
+    }
+    m
+}
+
+pub fn mat_vec(m: &Matrix, v: &[f32]) -> Vec<f32>
+{
+    m.iter()
+        .map(|row| row.iter().zip(v.iter()).map(|(a, b)| a * b).sum())
+        .collect()
+}
+
+pub fn mat_mul(a: &Matrix, b: &Matrix) -> Matrix
+{
+    let n = b.first().map_or(0, |row| row.len());
+    let mut out = vec![vec![0.0; n]; a.len()];
+
+    for (out_row, a_row) in out.iter_mut().zip(a.iter())
+    {
+        for (&a_ik, b_row) in a_row.iter().zip(b.iter())
+        {
+            for (o, &b_kj) in out_row.iter_mut().zip(b_row.iter())
+            {
+                *o += a_ik * b_kj;
+            }
+        }
+    }
+
+    out
+}
+
+// LU decomposition with partial pivoting, returns the packed factors, the row permutation
+// and the sign of the permutation
+fn lu(m: &Matrix) -> (Matrix, Vec<usize>, f32)
+{
+    let dim = m.len();
+    let mut lu = m.clone();
+    let mut perm: Vec<usize> = (0..dim).collect();
+    let mut sign = 1.0;
+
+    for k in 0..dim
+    {
+        let mut pivot = k;
+        for i in k + 1..dim
+        {
+            if lu[i][k].abs() > lu[pivot][k].abs()
+            {
+                pivot = i;
+            }
+        }
+
+        if pivot != k
+        {
+            lu.swap(pivot, k);
+            perm.swap(pivot, k);
+            sign = -sign;
+        }
+
+        let diag = lu[k][k];
+        if diag == 0.0
+        {
+            continue;
+        }
+
+        let (upper, lower) = lu.split_at_mut(k + 1);
+        let pivot_row = &upper[k];
+        for row in lower.iter_mut()
+        {
+            let factor = row[k] / diag;
+            row[k] = factor;
+            for (x, p) in row[k + 1..].iter_mut().zip(pivot_row[k + 1..].iter())
+            {
+                *x -= factor * p;
+            }
+        }
+    }
+
+    (lu, perm, sign)
+}
+
+// Solves m * x = b
+pub fn solve(m: &Matrix, b: &[f32]) -> Vec<f32>
+{
+    let dim = m.len();
+    let (lu, perm, _) = lu(m);
+
+    let mut x: Vec<f32> = perm.iter().map(|&p| b[p]).collect();
+    for i in 0..dim
+    {
+        for j in 0..i
+        {
+            x[i] -= lu[i][j] * x[j];
+        }
+    }
+
+    for i in (0..dim).rev()
+    {
+        for j in i + 1..dim
+        {
+            x[i] -= lu[i][j] * x[j];
+        }
+        x[i] /= lu[i][i];
+    }
+
+    x
+}
+
+pub fn log_abs_det(m: &Matrix) -> f32
+{
+    let (lu, _, _) = lu(m);
+    (0..m.len()).map(|i| lu[i][i].abs().ln()).sum()
+}
+
+// Matrix exponential by scaling and squaring of a truncated Taylor series
+pub fn expm(m: &Matrix) -> Matrix
+{
+    let dim = m.len();
+    let norm = m.iter()
+        .map(|row| row.iter().map(|x| x.abs()).sum::<f32>())
+        .fold(0.0, f32::max);
+
+    let mut squarings = 0;
+    let mut scale = 1.0;
+    while norm * scale > 0.5
+    {
+        scale *= 0.5;
+        squarings += 1;
+    }
+
+    let scaled: Matrix = m.iter()
+        .map(|row| row.iter().map(|x| x * scale).collect())
+        .collect();
+
+    let mut out = identity(dim);
+    let mut term = identity(dim);
+    for k in 1..=12
+    {
+        term = mat_mul(&term, &scaled);
+        for row in term.iter_mut()
+        {
+            for x in row.iter_mut()
+            {
+                *x /= k as f32;
+            }
+        }
+
+        for (out_row, term_row) in out.iter_mut().zip(term.iter())
+        {
+            for (o, t) in out_row.iter_mut().zip(term_row.iter())
+            {
+                *o += t;
+            }
+        }
+    }
+
+    for _ in 0..squarings
+    {
+        out = mat_mul(&out, &out);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assert_matrices_close(actual: &Matrix, expected: &Matrix, tolerance: f32)
+    {
+        for (a, e) in actual.iter().flatten().zip(expected.iter().flatten())
+        {
+            assert!((a - e).abs() <= tolerance * e.abs().max(1.0), "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    // Needs a row swap to factor since its leading entry is zero
+    fn pivoted() -> Matrix
+    {
+        vec![vec![0.0, 2.0, 1.0], vec![1.0, -1.0, 0.5], vec![3.0, 0.0, -2.0]]
+    }
+
+    #[test]
+    fn expm_of_a_diagonal_is_the_elementwise_exp()
+    {
+        // Large enough entries to need several squarings
+        let values = [0.0f32, 1.0, -2.0, 3.5];
+        let mut diagonal = vec![vec![0.0; 4]; 4];
+        let mut expected = identity(4);
+        for (i, &value) in values.iter().enumerate()
+        {
+            diagonal[i][i] = value;
+            expected[i][i] = value.exp();
+        }
+        assert_matrices_close(&expm(&diagonal), &expected, 1e-5);
+    }
+
+    #[test]
+    fn expm_matches_closed_forms()
+    {
+        // Nilpotent, the series stops after the linear term
+        assert_matrices_close(&expm(&vec![vec![0.0, 1.0], vec![0.0, 0.0]]), &vec![vec![1.0, 1.0], vec![0.0, 1.0]], 1e-6);
+
+        // Generator of rotations by `angle`
+        let angle: f32 = 2.5;
+        let rotation = expm(&vec![vec![0.0, -angle], vec![angle, 0.0]]);
+        let expected = vec![vec![angle.cos(), -angle.sin()], vec![angle.sin(), angle.cos()]];
+        assert_matrices_close(&rotation, &expected, 1e-5);
+
+        // expm(A) expm(-A) = I
+        let m = pivoted();
+        let negated: Matrix = m.iter().map(|row| row.iter().map(|x| -x).collect()).collect();
+        assert_matrices_close(&mat_mul(&expm(&m), &expm(&negated)), &identity(3), 1e-4);
+    }
+
+    #[test]
+    fn solve_round_trips()
+    {
+        let m = pivoted();
+        for b in [vec![1.0, 0.0, 0.0], vec![0.5, -2.0, 3.0], vec![10.0, 1e-3, -7.0]]
+        {
+            let x = solve(&m, &b);
+            assert_matrices_close(&vec![mat_vec(&m, &x)], &vec![b], 1e-5);
+        }
+        assert_eq!(solve(&identity(3), &[1.0, 2.0, 3.0]), vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn log_abs_det_matches_known_determinants()
+    {
+        let close = |m: &Matrix, det: f32| assert!((log_abs_det(m) - det.abs().ln()).abs() < 1e-5, "{:?}", m);
+
+        close(&identity(3), 1.0);
+        close(&vec![vec![2.0, 0.0], vec![0.0, -3.0]], -6.0);
+        close(&vec![vec![2.0, 5.0, -1.0], vec![0.0, 0.5, 4.0], vec![0.0, 0.0, 3.0]], 3.0);
+        close(&vec![vec![0.0, 1.0], vec![1.0, 0.0]], -1.0);
+        // 0 * (2 - 0) - 2 * (-2 - 1.5) + 1 * (0 + 3)
+        close(&pivoted(), 10.0);
+    }
+
+    #[test]
+    fn mat_mul_by_the_identity_is_a_no_op()
+    {
+        let m = pivoted();
+        assert_eq!(mat_mul(&m, &identity(3)), m);
+        assert_eq!(mat_mul(&identity(3), &m), m);
+        assert_eq!(mat_vec(&m, &[1.0, 0.0, 0.0]), vec![0.0, 1.0, 3.0]);
+    }
+}
@@ -0,0 +1,283 @@
+// How far a program's outputs are from the expected ones over a set of examples, lower is
+// better. Outputs and targets are paired by position.
+pub trait Loss
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32;
+}
+
+fn mean<F: Fn(f32, f32) -> f32>(outputs: &[f32], targets: &[f32], f: F) -> f32
+{
+    outputs.iter()
+        .zip(targets.iter())
+        .map(|(&output, &target)| f(output, target))
+        .sum::<f32>() / outputs.len() as f32
+}
+
+// Mean squared error
+pub struct Mse;
+
+impl Loss for Mse
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        mean(outputs, targets, |output, target| (output - target) * (output - target))
+    }
+}
+
+// Mean absolute error
+pub struct Mae;
+
+impl Loss for Mae
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        mean(outputs, targets, |output, target| (output - target).abs())
+    }
+}
+
+// Squared error up to `delta`, linear beyond it, averaged
+pub struct Huber
+{
+    pub delta: f32
+}
+
+impl Huber
+{
+    pub fn new(delta: f32) -> Self
+    {
+        Self {delta}
+    }
+}
+
+impl Default for Huber
+{
+    fn default() -> Self
+    {
+        Self::new(1.0)
+    }
+}
+
+impl Loss for Huber
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        mean(outputs, targets, |output, target| {
+            let error = (output - target).abs();
+            if error <= self.delta {0.5 * error * error} else {self.delta * (error - 0.5 * self.delta)}
+        })
+    }
+}
+
+// Mean of ln(cosh(error)), written as |e| + ln(1 + e^(-2|e|)) - ln 2 so large errors do not
+// overflow cosh
+pub struct LogCosh;
+
+impl Loss for LogCosh
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        mean(outputs, targets, |output, target| {
+            let error = (output - target).abs();
+            error + (-2.0 * error).exp().ln_1p() - std::f32::consts::LN_2
+        })
+    }
+}
+
+// Mean of |error| / |target|, with |target| floored at `epsilon` for zero targets
+pub struct RelativeError
+{
+    pub epsilon: f32
+}
+
+impl RelativeError
+{
+    pub fn new(epsilon: f32) -> Self
+    {
+        Self {epsilon}
+    }
+}
+
+impl Default for RelativeError
+{
+    fn default() -> Self
+    {
+        Self::new(1e-8)
+    }
+}
+
+impl Loss for RelativeError
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        mean(outputs, targets, |output, target| (output - target).abs() / target.abs().max(self.epsilon))
+    }
+}
+
+fn matches(output: f32, target: f32, tolerance: f32) -> bool
+{
+    (output - target).abs() <= tolerance
+}
+
+// Number of examples whose output is off by more than `tolerance`, for integer and boolean
+// tasks. NaN outputs never match.
+pub struct ExactMatch
+{
+    pub tolerance: f32
+}
+
+impl ExactMatch
+{
+    pub fn new(tolerance: f32) -> Self
+    {
+        Self {tolerance}
+    }
+}
+
+impl Default for ExactMatch
+{
+    fn default() -> Self
+    {
+        Self::new(0.0)
+    }
+}
+
+impl Loss for ExactMatch
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        outputs.iter()
+            .zip(targets.iter())
+            .filter(|(&output, &target)| !matches(output, target, self.tolerance))
+            .count() as f32
+    }
+}
+
+// 0 if every output is within `tolerance` of its target, 1 otherwise
+pub struct Success
+{
+    pub tolerance: f32
+}
+
+impl Success
+{
+    pub fn new(tolerance: f32) -> Self
+    {
+        Self {tolerance}
+    }
+}
+
+impl Default for Success
+{
+    fn default() -> Self
+    {
+        Self::new(0.0)
+    }
+}
+
+impl Loss for Success
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        let solved = outputs.iter()
+            .zip(targets.iter())
+            .all(|(&output, &target)| matches(output, target, self.tolerance));
+        if solved {0.0} else {1.0}
+    }
+}
+
+// Sum of weight * loss over the terms, e.g. MSE plus a bonus for exactly solving the task
+pub struct Weighted
+{
+    pub terms: Vec<(f32, Box<dyn Loss>)>
+}
+
+impl Weighted
+{
+    pub fn new(terms: Vec<(f32, Box<dyn Loss>)>) -> Self
+    {
+        Self {terms}
+    }
+}
+
+impl Loss for Weighted
+{
+    fn loss(&self, outputs: &[f32], targets: &[f32]) -> f32
+    {
+        self.terms.iter()
+            .map(|(weight, loss)| weight * loss.loss(outputs, targets))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32)
+    {
+        assert!((actual - expected).abs() <= 1e-5 * expected.abs().max(1.0), "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn huber_is_quadratic_up_to_delta_and_linear_beyond()
+    {
+        let huber = Huber::new(2.0);
+        assert_close(huber.loss(&[1.5], &[0.0]), 0.5 * 1.5 * 1.5);
+        assert_close(huber.loss(&[2.0], &[0.0]), 2.0);
+        assert_close(huber.loss(&[0.0], &[5.0]), 2.0 * (5.0 - 1.0));
+
+        // Both pieces meet with the same value and slope at delta
+        let h = 1e-2;
+        let at = huber.loss(&[2.0], &[0.0]);
+        let left = (at - huber.loss(&[2.0 - h], &[0.0])) / h;
+        let right = (huber.loss(&[2.0 + h], &[0.0]) - at) / h;
+        assert!((left - 2.0).abs() < h && (right - 2.0).abs() < h, "slopes {} and {}", left, right);
+
+        assert_close(Huber::default().loss(&[0.5, 3.0], &[0.0, 0.0]), (0.125 + 2.5) / 2.0);
+    }
+
+    #[test]
+    fn log_cosh_matches_cosh_and_does_not_overflow()
+    {
+        for error in [0.0f32, 0.1, -0.7, 2.0, 10.0]
+        {
+            assert_close(LogCosh.loss(&[error], &[0.0]), error.cosh().ln());
+        }
+
+        // cosh overflows f32 past about 89
+        let loss = LogCosh.loss(&[1000.0], &[0.0]);
+        assert!(loss.is_finite());
+        assert_close(loss, 1000.0 - std::f32::consts::LN_2);
+    }
+
+    #[test]
+    fn relative_error_floors_zero_targets_at_epsilon()
+    {
+        assert_close(RelativeError::default().loss(&[3.0, -1.0], &[2.0, -2.0]), (0.5 + 0.5) / 2.0);
+        assert_close(RelativeError::new(0.1).loss(&[0.5], &[0.0]), 5.0);
+        assert_close(RelativeError::new(0.1).loss(&[0.5], &[1.0]), 0.5);
+        assert!(RelativeError::default().loss(&[1e-3], &[0.0]).is_finite());
+    }
+
+    #[test]
+    fn exact_match_and_success_count_misses()
+    {
+        let outputs = [1.0, 2.05, f32::NAN, 4.0];
+        let targets = [1.0, 2.0, 3.0, 5.0];
+        assert_eq!(ExactMatch::default().loss(&outputs, &targets), 3.0);
+        assert_eq!(ExactMatch::new(0.1).loss(&outputs, &targets), 2.0);
+        assert_eq!(Success::new(0.1).loss(&outputs, &targets), 1.0);
+        assert_eq!(Success::new(0.1).loss(&outputs[..2], &targets[..2]), 0.0);
+    }
+
+    #[test]
+    fn weighted_sums_its_terms()
+    {
+        let outputs = [1.0, 4.0];
+        let targets = [0.0, 2.0];
+        let weighted = Weighted::new(vec![(2.0, Box::new(Mse)), (0.5, Box::new(Mae)), (10.0, Box::new(Success::default()))]);
+        assert_close(weighted.loss(&outputs, &targets), 2.0 * 2.5 + 0.5 * 1.5 + 10.0);
+        assert_eq!(Weighted::new(Vec::new()).loss(&outputs, &targets), 0.0);
+    }
+}
@@ -1,10 +1,12 @@
-mod rng;
-mod dist;
 //mod exp1;
 mod exp2;
+mod report;
+
+use gmp::loss::Mse;
+use gmp::sampling::Sampling;
 
 fn main() 
 {
-    //exp1::run_exp1();
-    exp2::run_exp2(0.001);
+    //exp1::run_exp1(Sampling::Independent, &Mse);
+    exp2::run_exp2(0.001, Sampling::Independent, &Mse);
 }
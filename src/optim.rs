@@ -0,0 +1,276 @@
+// Gradient types flatten to a plain vector so one optimizer can drive every distribution
+pub trait Gradient
+{
+    fn flatten(&self) -> Vec<f32>;
+    // Rebuilds a gradient of the same shape as `self` from flattened values
+    fn unflatten(&self, values: &[f32]) -> Self;
+}
+
+impl Gradient for Vec<f32>
+{
+    fn flatten(&self) -> Vec<f32>
+    {
+        self.clone()
+    }
+
+    fn unflatten(&self, values: &[f32]) -> Self
+    {
+        values.to_vec()
+    }
+}
+
+// Turns the gradient of each hole into the direction handed to `Distribution::update`
+// together with the rate. `slot` identifies the hole so state is kept per parameter.
+pub trait Optimizer
+{
+    fn step(&mut self, slot: usize, grad: &[f32]) -> Vec<f32>;
+    // Drops the moments of every slot. The trainer calls it when a run starts and again after
+    // each restart, so a restarted search does not inherit momentum from the one it replaced.
+    fn reset(&mut self);
+}
+
+// State vector of `slot`, zeroed when it is first used or its length changes
+fn slot_state(states: &mut Vec<Vec<f32>>, slot: usize, len: usize) -> &mut Vec<f32>
+{
+    if states.len() <= slot
+    {
+        states.resize(slot + 1, Vec::new());
+    }
+
+    if states[slot].len() != len
+    {
+        states[slot] = vec![0.0; len];
+    }
+
+    &mut states[slot]
+}
+
+// The gradient itself, param -= rate * grad
+pub struct Sgd;
+
+impl Optimizer for Sgd
+{
+    fn step(&mut self, _slot: usize, grad: &[f32]) -> Vec<f32>
+    {
+        grad.to_vec()
+    }
+
+    fn reset(&mut self) {}
+}
+
+// Heavy-ball momentum, v = beta * v + grad
+pub struct Momentum
+{
+    pub beta: f32,
+    velocity: Vec<Vec<f32>>
+}
+
+impl Momentum
+{
+    pub fn new(beta: f32) -> Self
+    {
+        Self {beta, velocity: Vec::new()}
+    }
+}
+
+impl Optimizer for Momentum
+{
+    fn step(&mut self, slot: usize, grad: &[f32]) -> Vec<f32>
+    {
+        let velocity = slot_state(&mut self.velocity, slot, grad.len());
+        for (v, g) in velocity.iter_mut().zip(grad.iter())
+        {
+            *v = self.beta * *v + g;
+        }
+        velocity.clone()
+    }
+
+    fn reset(&mut self)
+    {
+        self.velocity.clear();
+    }
+}
+
+// Adam, bias-corrected first and second moments scaled per coordinate
+pub struct Adam
+{
+    pub beta1: f32,
+    pub beta2: f32,
+    pub epsilon: f32,
+    first: Vec<Vec<f32>>,
+    second: Vec<Vec<f32>>,
+    steps: Vec<i32>
+}
+
+impl Adam
+{
+    pub fn new(beta1: f32, beta2: f32, epsilon: f32) -> Self
+    {
+        Self {beta1, beta2, epsilon, first: Vec::new(), second: Vec::new(), steps: Vec::new()}
+    }
+}
+
+impl Default for Adam
+{
+    fn default() -> Self
+    {
+        Self::new(0.9, 0.999, 1e-8)
+    }
+}
+
+impl Optimizer for Adam
+{
+    fn step(&mut self, slot: usize, grad: &[f32]) -> Vec<f32>
+    {
+        if self.steps.len() <= slot
+        {
+            self.steps.resize(slot + 1, 0);
+        }
+        self.steps[slot] += 1;
+        let t = self.steps[slot];
+
+        let first = slot_state(&mut self.first, slot, grad.len());
+        let second = slot_state(&mut self.second, slot, grad.len());
+        let correction1 = 1.0 - self.beta1.powi(t);
+        let correction2 = 1.0 - self.beta2.powi(t);
+
+        let mut direction = Vec::with_capacity(grad.len());
+        for ((m, v), g) in first.iter_mut().zip(second.iter_mut()).zip(grad.iter())
+        {
+            *m = self.beta1 * *m + (1.0 - self.beta1) * g;
+            *v = self.beta2 * *v + (1.0 - self.beta2) * g * g;
+            direction.push((*m / correction1) / ((*v / correction2).sqrt() + self.epsilon));
+        }
+
+        direction
+    }
+
+    fn reset(&mut self)
+    {
+        self.first.clear();
+        self.second.clear();
+        self.steps.clear();
+    }
+}
+
+// Natural gradient divided by the running RMS of its own norm. The whole slot shares one
+// scale, so unlike Adam the step keeps the natural gradient's direction and only its
+// length adapts.
+pub struct AdaptiveNatural
+{
+    pub beta: f32,
+    pub epsilon: f32,
+    norms: Vec<(f32, i32)>
+}
+
+impl AdaptiveNatural
+{
+    pub fn new(beta: f32, epsilon: f32) -> Self
+    {
+        Self {beta, epsilon, norms: Vec::new()}
+    }
+}
+
+impl Default for AdaptiveNatural
+{
+    fn default() -> Self
+    {
+        Self::new(0.99, 1e-8)
+    }
+}
+
+impl Optimizer for AdaptiveNatural
+{
+    fn step(&mut self, slot: usize, grad: &[f32]) -> Vec<f32>
+    {
+        if self.norms.len() <= slot
+        {
+            self.norms.resize(slot + 1, (0.0, 0));
+        }
+
+        let sq: f32 = grad.iter().map(|g| g * g).sum::<f32>() / grad.len().max(1) as f32;
+        let (mean_sq, steps) = &mut self.norms[slot];
+        *mean_sq = self.beta * *mean_sq + (1.0 - self.beta) * sq;
+        *steps += 1;
+
+        let rms = (*mean_sq / (1.0 - self.beta.powi(*steps))).sqrt();
+        grad.iter().map(|g| g / (rms + self.epsilon)).collect()
+    }
+
+    fn reset(&mut self)
+    {
+        self.norms.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assert_close(actual: &[f32], expected: &[f32])
+    {
+        assert_eq!(actual.len(), expected.len());
+        assert!(actual.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() < 1e-5), "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn sgd_passes_the_gradient_through()
+    {
+        assert_eq!(Sgd.step(3, &[1.0, -2.0]), vec![1.0, -2.0]);
+    }
+
+    #[test]
+    fn momentum_accumulates_per_slot()
+    {
+        let mut momentum = Momentum::new(0.5);
+        assert_close(&momentum.step(0, &[1.0, 2.0]), &[1.0, 2.0]);
+        assert_close(&momentum.step(0, &[1.0, 2.0]), &[1.5, 3.0]);
+        assert_close(&momentum.step(1, &[4.0]), &[4.0]);
+        assert_close(&momentum.step(0, &[0.0, 0.0]), &[0.75, 1.5]);
+
+        momentum.reset();
+        assert_close(&momentum.step(0, &[1.0, 2.0]), &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn adam_corrects_the_bias_of_its_first_steps()
+    {
+        // Without the correction the first step would be 0.1 / sqrt(0.001) times the sign
+        let mut adam = Adam::default();
+        assert_close(&adam.step(0, &[1e-2, -1e3]), &[1.0, -1.0]);
+
+        let mut adam = Adam::default();
+        adam.step(0, &[1.0]);
+        let (m, v) = (0.9 * 0.1 + 0.1 * 3.0, 0.999 * 0.001 + 0.001 * 9.0);
+        let expected = (m / (1.0 - 0.9f32.powi(2))) / (v / (1.0 - 0.999f32.powi(2))).sqrt();
+        assert_close(&adam.step(0, &[3.0]), &[expected]);
+
+        // Other slots and a reset start from step one
+        assert_close(&adam.step(1, &[-5.0]), &[-1.0]);
+        adam.reset();
+        assert_close(&adam.step(0, &[3.0]), &[1.0]);
+    }
+
+    #[test]
+    fn adaptive_natural_shares_one_scale_per_slot()
+    {
+        let mut optimizer = AdaptiveNatural::default();
+        let rms = (12.5f32).sqrt();
+        assert_close(&optimizer.step(0, &[3.0, 4.0]), &[3.0 / rms, 4.0 / rms]);
+
+        // The direction is kept and only the length is normalised
+        let mut scaled = AdaptiveNatural::default();
+        assert_close(&scaled.step(0, &[30.0, 40.0]), &[3.0 / rms, 4.0 / rms]);
+
+        // A larger gradient after a small one takes a longer step
+        let mut optimizer = AdaptiveNatural::new(0.5, 1e-8);
+        optimizer.step(0, &[1.0]);
+        let mean_sq = (0.5 * 0.5 + 0.5 * 4.0) / (1.0 - 0.25);
+        assert_close(&optimizer.step(0, &[2.0]), &[2.0 / f32::sqrt(mean_sq)]);
+        assert_close(&optimizer.step(1, &[2.0]), &[1.0]);
+
+        optimizer.reset();
+        assert_close(&optimizer.step(0, &[2.0]), &[1.0]);
+    }
+}
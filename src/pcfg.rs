@@ -0,0 +1,590 @@
+use crate::dist::{Categorical, Distribution};
+use crate::estimator::GradientEstimator;
+use crate::expr::{BinOp, CmpOp, Expr, Program};
+use crate::rng::RandomSource;
+
+// Productions of the expression nonterminal E:
+//
+//     E -> variable | constant | E op E | if E cmp E { E } else { E }
+//
+// Terminals come first so the productions allowed at the depth limit are a prefix of all
+// of them. Conditionals are only produced when there are comparisons to choose from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grammar
+{
+    pub variables: Vec<String>,
+    pub constants: Vec<f32>,
+    pub operators: Vec<BinOp>,
+    pub comparisons: Vec<CmpOp>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Production
+{
+    Var(usize),
+    Const(f32),
+    Binary(BinOp),
+    If
+}
+
+impl Production
+{
+    // Number of E children
+    fn arity(self) -> f32
+    {
+        match self
+        {
+            Production::Var(_) | Production::Const(_) => 0.0,
+            Production::Binary(_) => 2.0,
+            Production::If => 4.0
+        }
+    }
+}
+
+impl Grammar
+{
+    // Arithmetic over the variables and constants, with the comparisons the sketches use
+    pub fn new(variables: &[&str], constants: Vec<f32>) -> Self
+    {
+        Self {
+            variables: variables.iter().map(|&v| v.to_owned()).collect(),
+            constants,
+            operators: vec![BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div],
+            comparisons: vec![CmpOp::Gt, CmpOp::Lt, CmpOp::Eq]
+        }
+    }
+
+    pub fn num_terminals(&self) -> usize
+    {
+        self.variables.len() + self.constants.len()
+    }
+
+    pub fn num_productions(&self) -> usize
+    {
+        self.num_terminals() + self.operators.len() + usize::from(!self.comparisons.is_empty())
+    }
+
+    pub fn production(&self, index: usize) -> Production
+    {
+        let mut index = index;
+        if index < self.variables.len()
+        {
+            return Production::Var(index);
+        }
+
+        index -= self.variables.len();
+        if index < self.constants.len()
+        {
+            return Production::Const(self.constants[index]);
+        }
+
+        index -= self.constants.len();
+        if index < self.operators.len()
+        {
+            return Production::Binary(self.operators[index]);
+        }
+
+        Production::If
+    }
+
+    // Production at the root of `expr`, if the grammar can produce it. Constants are matched
+    // by value.
+    fn production_of(&self, expr: &Expr) -> Option<usize>
+    {
+        let terminals = self.num_terminals();
+        match expr
+        {
+            Expr::Var(index) if *index < self.variables.len() => Some(*index),
+            Expr::Num(value) => self.constants.iter().position(|c| c == value).map(|i| self.variables.len() + i),
+            Expr::Binary(op, _, _) => self.operators.iter().position(|o| o == op).map(|i| terminals + i),
+            Expr::If(..) if !self.comparisons.is_empty() => Some(terminals + self.operators.len()),
+            _ => None
+        }
+    }
+
+    pub fn program(&self, expr: Expr) -> Program
+    {
+        Program {expr, variables: self.variables.clone()}
+    }
+}
+
+// Distribution over expression trees of depth at most `max_depth`, the root being at depth
+// zero. Every depth has its own choice of E production and, below the limit, of comparison,
+// so shallow and deep nodes learn different shapes. A tree has exactly one derivation,
+// which is recovered from the tree itself.
+#[derive(Clone)]
+pub struct PcfgDistribution
+{
+    grammar: Grammar,
+    max_depth: usize,
+    // Choice of E production at every depth, over terminals only at `max_depth`
+    exprs: Vec<Categorical>,
+    // Choice of comparison at every depth below the limit, empty without comparisons
+    cmps: Vec<Categorical>
+}
+
+impl PcfgDistribution
+{
+    // Uniform over the productions at every depth
+    pub fn new(estimator: GradientEstimator, grammar: Grammar, max_depth: usize) -> Self
+    {
+        assert!(grammar.num_terminals() > 0, "the grammar has no variables or constants");
+
+        let exprs = (0..=max_depth)
+            .map(|depth| {
+                let size = if depth == max_depth {grammar.num_terminals()} else {grammar.num_productions()};
+                Categorical::new(estimator, vec![0.0; size])
+            })
+            .collect();
+
+        let num_cmps = if grammar.comparisons.is_empty() {0} else {max_depth};
+        let cmps = (0..num_cmps)
+            .map(|_| Categorical::new(estimator, vec![0.0; grammar.comparisons.len()]))
+            .collect();
+
+        Self {grammar, max_depth, exprs, cmps}
+    }
+
+    pub fn grammar(&self) -> &Grammar
+    {
+        &self.grammar
+    }
+
+    pub fn max_depth(&self) -> usize
+    {
+        self.max_depth
+    }
+
+    // Choice points are numbered E productions by depth, then comparisons by depth
+    fn num_points(&self) -> usize
+    {
+        self.exprs.len() + self.cmps.len()
+    }
+
+    fn point(&self, index: usize) -> &Categorical
+    {
+        if index < self.exprs.len() {&self.exprs[index]} else {&self.cmps[index - self.exprs.len()]}
+    }
+
+    fn point_mut(&mut self, index: usize) -> &mut Categorical
+    {
+        let num_exprs = self.exprs.len();
+        if index < num_exprs {&mut self.exprs[index]} else {&mut self.cmps[index - num_exprs]}
+    }
+
+    fn point_size(&self, index: usize) -> usize
+    {
+        if index < self.exprs.len() && index == self.max_depth
+        {
+            return self.grammar.num_terminals();
+        }
+        if index < self.exprs.len() {self.grammar.num_productions()} else {self.grammar.comparisons.len()}
+    }
+
+    fn build<F>(&self, depth: usize, choose: &mut F) -> Expr
+    where
+        F: FnMut(&Categorical) -> usize
+    {
+        let production = self.grammar.production(choose(&self.exprs[depth]));
+        let mut child = || Box::new(self.build(depth + 1, choose));
+        match production
+        {
+            Production::Var(index) => Expr::Var(index),
+            Production::Const(value) => Expr::Num(value),
+            Production::Binary(op) =>
+            {
+                let lhs = child();
+                Expr::Binary(op, lhs, child())
+            }
+            Production::If =>
+            {
+                let (lhs, rhs, then, otherwise) = (child(), child(), child(), child());
+                let cmp = self.grammar.comparisons[choose(&self.cmps[depth])];
+                Expr::If(cmp, lhs, rhs, then, otherwise)
+            }
+        }
+    }
+
+    // Appends the (choice point, choice) pairs that derive `expr`, false if none do
+    fn derive(&self, expr: &Expr, depth: usize, choices: &mut Vec<(usize, usize)>) -> bool
+    {
+        let production = match self.grammar.production_of(expr)
+        {
+            Some(production) if production < self.point_size(depth) => production,
+            _ => return false
+        };
+        choices.push((depth, production));
+
+        match expr
+        {
+            Expr::Binary(_, lhs, rhs) => self.derive(lhs, depth + 1, choices) && self.derive(rhs, depth + 1, choices),
+            Expr::If(cmp, lhs, rhs, then, otherwise) =>
+            {
+                let derived = [lhs, rhs, then, otherwise].iter().all(|child| self.derive(child, depth + 1, choices));
+                match self.grammar.comparisons.iter().position(|c| c == cmp)
+                {
+                    Some(index) if derived =>
+                    {
+                        choices.push((self.exprs.len() + depth, index));
+                        true
+                    }
+                    _ => false
+                }
+            }
+            _ => true
+        }
+    }
+
+    fn prob(dist: &Categorical, index: usize) -> f32
+    {
+        dist.log_prob(index).exp()
+    }
+
+    // sum_k visits_k * value(k) over the choice points, where visits_k is the expected number
+    // of times a tree uses point k, and its gradient with respect to every logit. Values are
+    // propagated up from the depth limit as the expected total of a subtree rooted at an E
+    // node of each depth.
+    fn expected<V, G>(&self, value: V, value_grad: G) -> (f32, Vec<f32>)
+    where
+        V: Fn(usize) -> f32,
+        G: Fn(usize) -> Vec<f32>
+    {
+        let num_exprs = self.exprs.len();
+        let if_index = self.grammar.num_productions() - 1;
+        let has_if = !self.cmps.is_empty();
+
+        let mut visits = vec![1.0; num_exprs];
+        for depth in 1..num_exprs
+        {
+            let dist = &self.exprs[depth - 1];
+            let children: f32 = (0..self.point_size(depth - 1))
+                .map(|k| Self::prob(dist, k) * self.grammar.production(k).arity())
+                .sum();
+            visits[depth] = visits[depth - 1] * children;
+        }
+
+        let mut grad = vec![Vec::new(); self.num_points()];
+        let mut below = 0.0;
+        for depth in (0..num_exprs).rev()
+        {
+            let dist = &self.exprs[depth];
+            let size = self.point_size(depth);
+
+            // What each production adds below the node
+            let gains: Vec<f32> = (0..size)
+                .map(|k| {
+                    let cmp = if has_if && k == if_index {value(num_exprs + depth)} else {0.0};
+                    self.grammar.production(k).arity() * below + cmp
+                })
+                .collect();
+            let mean: f32 = gains.iter().enumerate().map(|(k, gain)| Self::prob(dist, k) * gain).sum();
+
+            grad[depth] = value_grad(depth).iter()
+                .zip(gains.iter())
+                .enumerate()
+                .map(|(k, (g, gain))| visits[depth] * (g + Self::prob(dist, k) * (gain - mean)))
+                .collect();
+
+            if has_if && depth < self.max_depth
+            {
+                let p_if = Self::prob(dist, if_index);
+                grad[num_exprs + depth] = value_grad(num_exprs + depth).iter()
+                    .map(|g| visits[depth] * p_if * g)
+                    .collect();
+            }
+
+            below = value(depth) + mean;
+        }
+
+        (below, grad.concat())
+    }
+}
+
+impl Distribution for PcfgDistribution
+{
+    type SampleType = Expr;
+    type GradType = Vec<f32>;
+
+    fn sample<R: RandomSource + ?Sized>(&self, rng: &mut R) -> Self::SampleType
+    {
+        self.build(0, &mut |dist| dist.sample(rng))
+    }
+
+    // Most likely production at every choice point, which need not be the most likely tree
+    fn argmax(&self) -> Self::SampleType
+    {
+        self.build(0, &mut |dist| dist.argmax())
+    }
+
+    fn log_prob(&self, x: Self::SampleType) -> f32
+    {
+        let mut choices = Vec::new();
+        if !self.derive(&x, 0, &mut choices)
+        {
+            return f32::NEG_INFINITY;
+        }
+
+        choices.iter().map(|&(point, choice)| self.point(point).log_prob(choice)).sum()
+    }
+
+    // Every choice point gets the traces of the samples that used it, once per use. Each
+    // point's estimate is rescaled from its own number of traces to the population size, so
+    // the sum over points is the score function of the whole derivation.
+    fn grad(&self, traces: Vec<(Self::SampleType, f32)>) -> Self::GradType
+    {
+        let num_samples = traces.len() as f32;
+        let mut point_traces = vec![Vec::new(); self.num_points()];
+        let mut choices = Vec::new();
+
+        for (expr, score) in traces.iter()
+        {
+            choices.clear();
+            if self.derive(expr, 0, &mut choices)
+            {
+                for &(point, choice) in choices.iter()
+                {
+                    point_traces[point].push((choice, *score));
+                }
+            }
+        }
+
+        point_traces.into_iter()
+            .enumerate()
+            .flat_map(|(point, traces)| {
+                if traces.is_empty()
+                {
+                    return vec![0.0; self.point_size(point)];
+                }
+
+                let scale = traces.len() as f32 / num_samples;
+                self.point(point).grad(traces).into_iter().map(|g| g * scale).collect()
+            })
+            .collect()
+    }
+
+    fn update(&mut self, grad: Self::GradType, rate: f32)
+    {
+        let mut offset = 0;
+        for point in 0..self.num_points()
+        {
+            let size = self.point_size(point);
+            self.point_mut(point).update(grad[offset..offset + size].to_vec(), rate);
+            offset += size;
+        }
+    }
+
+    // Every choice point's slice goes through the preconditioning of its own categorical
+    fn precondition(&self, grad: Self::GradType) -> Self::GradType
+    {
+        let mut offset = 0;
+        let mut preconditioned = Vec::with_capacity(grad.len());
+        for point in 0..self.num_points()
+        {
+            let size = self.point_size(point);
+            preconditioned.extend(self.point(point).precondition(grad[offset..offset + size].to_vec()));
+            offset += size;
+        }
+        preconditioned
+    }
+
+    // Entropy of the tree, i.e. of its derivation
+    fn entropy(&self) -> f32
+    {
+        self.expected(|point| self.point(point).entropy(), |point| self.point(point).entropy_grad()).0
+    }
+
+    fn kl(&self, other: &Self) -> f32
+    {
+        self.expected(|point| self.point(point).kl(other.point(point)), |point| self.point(point).kl_grad(other.point(point))).0
+    }
+
+    fn entropy_grad(&self) -> Self::GradType
+    {
+        self.expected(|point| self.point(point).entropy(), |point| self.point(point).entropy_grad()).1
+    }
+
+    fn kl_grad(&self, prior: &Self) -> Self::GradType
+    {
+        self.expected(|point| self.point(point).kl(prior.point(point)), |point| self.point(point).kl_grad(prior.point(point))).1
+    }
+
+    fn discrete(&self) -> bool
+    {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::rng::RNG;
+
+    // Every tree the grammar derives from an E node at `depth`
+    fn trees(grammar: &Grammar, depth: usize, max_depth: usize) -> Vec<Expr>
+    {
+        let mut exprs: Vec<Expr> = (0..grammar.num_terminals())
+            .map(|index| match grammar.production(index)
+            {
+                Production::Var(index) => Expr::Var(index),
+                Production::Const(value) => Expr::Num(value),
+                _ => unreachable!()
+            })
+            .collect();
+        if depth == max_depth
+        {
+            return exprs;
+        }
+
+        let children = trees(grammar, depth + 1, max_depth);
+        for &op in grammar.operators.iter()
+        {
+            for lhs in children.iter()
+            {
+                for rhs in children.iter()
+                {
+                    exprs.push(Expr::Binary(op, Box::new(lhs.clone()), Box::new(rhs.clone())));
+                }
+            }
+        }
+
+        for &cmp in grammar.comparisons.iter()
+        {
+            for lhs in children.iter()
+            {
+                for rhs in children.iter()
+                {
+                    for then in children.iter()
+                    {
+                        for otherwise in children.iter()
+                        {
+                            let [lhs, rhs, then, otherwise] = [lhs, rhs, then, otherwise].map(|child| Box::new(child.clone()));
+                            exprs.push(Expr::If(cmp, lhs, rhs, then, otherwise));
+                        }
+                    }
+                }
+            }
+        }
+        exprs
+    }
+
+    fn grammars() -> Vec<(Grammar, usize)>
+    {
+        let mut arithmetic = Grammar::new(&["x"], vec![1.0]);
+        arithmetic.operators = vec![BinOp::Add, BinOp::Mul];
+        arithmetic.comparisons = Vec::new();
+
+        let mut conditional = arithmetic.clone();
+        conditional.comparisons = vec![CmpOp::Gt, CmpOp::Lt];
+
+        vec![(arithmetic.clone(), 0), (arithmetic, 2), (conditional.clone(), 0), (conditional, 1)]
+    }
+
+    // Uniform distributions are a special case for the recursions, so the logits are moved
+    fn random(grammar: &Grammar, max_depth: usize, seed: u32) -> PcfgDistribution
+    {
+        let mut dist = PcfgDistribution::new(GradientEstimator::Dnes, grammar.clone(), max_depth);
+        let mut rng = RNG::new(seed);
+        let grad = (0..dist.entropy_grad().len()).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        dist.update(grad, 1.0);
+        dist
+    }
+
+    // Gradient of `f` by central differences in every logit
+    fn finite_differences<F: Fn(&PcfgDistribution) -> f32>(dist: &PcfgDistribution, f: F) -> Vec<f32>
+    {
+        const EPS: f32 = 1e-2;
+
+        let size = dist.entropy_grad().len();
+        (0..size)
+            .map(|i| {
+                let mut step = vec![0.0; size];
+                step[i] = 1.0;
+                let (mut up, mut down) = (dist.clone(), dist.clone());
+                up.update(step.clone(), -EPS);
+                down.update(step, EPS);
+                (f(&up) - f(&down)) / (2.0 * EPS)
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: f32, expected: f32, what: &str)
+    {
+        assert!((actual - expected).abs() < 1e-3 * expected.abs().max(1.0), "{}: {} != {}", what, actual, expected);
+    }
+
+    #[test]
+    fn pcfg_log_prob_sums_to_one()
+    {
+        for (seed, (grammar, max_depth)) in grammars().into_iter().enumerate()
+        {
+            let dist = random(&grammar, max_depth, seed as u32);
+            let total: f64 = trees(&grammar, 0, max_depth).into_iter()
+                .map(|tree| (dist.log_prob(tree) as f64).exp())
+                .sum();
+            assert!((total - 1.0).abs() < 1e-4, "max depth {}: total probability {}", max_depth, total);
+        }
+    }
+
+    #[test]
+    fn pcfg_entropy_and_kl_match_enumeration()
+    {
+        for (seed, (grammar, max_depth)) in grammars().into_iter().enumerate()
+        {
+            let dist = random(&grammar, max_depth, seed as u32);
+            let other = random(&grammar, max_depth, 100 + seed as u32);
+
+            let (mut entropy, mut kl) = (0.0, 0.0);
+            for tree in trees(&grammar, 0, max_depth)
+            {
+                let log_p = dist.log_prob(tree.clone()) as f64;
+                let log_q = other.log_prob(tree) as f64;
+                entropy -= log_p.exp() * log_p;
+                kl += log_p.exp() * (log_p - log_q);
+            }
+
+            assert_close(dist.entropy(), entropy as f32, "entropy");
+            assert_close(dist.kl(&other), kl as f32, "kl");
+        }
+    }
+
+    #[test]
+    fn pcfg_gradients_match_finite_differences()
+    {
+        for (seed, (grammar, max_depth)) in grammars().into_iter().enumerate()
+        {
+            let dist = random(&grammar, max_depth, seed as u32);
+            let prior = random(&grammar, max_depth, 100 + seed as u32);
+
+            let entropy = finite_differences(&dist, |dist| dist.entropy());
+            for (i, (&actual, &expected)) in dist.entropy_grad().iter().zip(entropy.iter()).enumerate()
+            {
+                assert_close(actual, expected, &format!("max depth {}, entropy logit {}", max_depth, i));
+            }
+
+            let kl = finite_differences(&dist, |dist| dist.kl(&prior));
+            for (i, (&actual, &expected)) in dist.kl_grad(&prior).iter().zip(kl.iter()).enumerate()
+            {
+                assert_close(actual, expected, &format!("max depth {}, kl logit {}", max_depth, i));
+            }
+        }
+    }
+
+    #[test]
+    fn pcfg_of_depth_zero_only_produces_terminals()
+    {
+        let grammar = Grammar::new(&["x", "y"], vec![1.0]);
+        let dist = PcfgDistribution::new(GradientEstimator::Dnes, grammar, 0);
+        let mut rng = RNG::new(3);
+
+        for _ in 0..100
+        {
+            assert!(matches!(dist.sample(&mut rng), Expr::Var(_) | Expr::Num(_)));
+        }
+        assert_eq!(dist.entropy_grad().len(), 3);
+        assert_close(dist.entropy(), 3f32.ln(), "entropy");
+
+        let sum = Expr::Binary(BinOp::Add, Box::new(Expr::Var(0)), Box::new(Expr::Var(1)));
+        assert_eq!(dist.log_prob(sum), f32::NEG_INFINITY);
+    }
+}
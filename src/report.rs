@@ -0,0 +1,31 @@
+use gmp::dataset::Dataset;
+use gmp::hole::HoleSet;
+use gmp::loss::Loss;
+use gmp::sketch::Sketch;
+use gmp::train::RunResult;
+
+// Prints the argmax program against the examples, why the run stopped and the best
+// programs it sampled
+pub fn report(hole_set: &HoleSet, sketch: &Sketch, result: &RunResult, dataset: &Dataset, loss: &dyn Loss)
+{
+    let program = sketch.instantiate(&hole_set.argmax());
+
+    println!("Expected outputs: {:?}", dataset.outputs[0]);
+    println!("Induction outputs: {:?}", dataset.predictions(&program));
+    println!("Argmax Loss: {}", dataset.loss(std::slice::from_ref(&program), loss));
+
+    println!("Stopped by {:?} after {} iterations and {} evaluations", result.stop_reason, result.log.len(), result.evaluations);
+    match result.solution
+    {
+        Some(solution) => println!("Solved after {} iterations and {} evaluations", solution.iters, solution.evaluations),
+        None => println!("Not solved")
+    }
+
+    println!("Best sampled programs:");
+    for entry in result.hall_of_fame.entries()
+    {
+        println!("    loss {} at iteration {}: {}", entry.loss, entry.iter, sketch.instantiate(&entry.assignment));
+    }
+
+    println!("Argmax program: {}", program);
+}
@@ -0,0 +1,133 @@
+use crate::rng::RandomSource;
+
+// Population used after each restart
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartStrategy
+{
+    // Back to the base population
+    Reset,
+    // The population grows by `factor` on every restart (IPOP)
+    Ipop {factor: f32},
+    // Alternates between IPOP-style large populations and small ones drawn between the base
+    // and half the current large one, whichever regime has used fewer evaluations (BIPOP)
+    Bipop {factor: f32}
+}
+
+// Detects stagnation and chooses the population of the next restart. A run has stagnated
+// when any discrete hole has collapsed below `entropy_threshold` nats, or when the mean loss
+// has not improved for `patience` iterations. Holes are checked one by one since a total
+// over many undecided holes can stay high while one of them has already collapsed.
+pub struct Restarts
+{
+    pub strategy: RestartStrategy,
+    pub entropy_threshold: f32,
+    pub patience: usize,
+    pub max_restarts: usize,
+    count: usize,
+    large: f32,
+    large_regime: bool,
+    large_evaluations: usize,
+    small_evaluations: usize
+}
+
+impl Restarts
+{
+    pub fn new(strategy: RestartStrategy, entropy_threshold: f32, patience: usize, max_restarts: usize) -> Self
+    {
+        Self {
+            strategy,
+            entropy_threshold,
+            patience,
+            max_restarts,
+            count: 0,
+            large: 0.0,
+            large_regime: true,
+            large_evaluations: 0,
+            small_evaluations: 0
+        }
+    }
+
+    pub fn count(&self) -> usize
+    {
+        self.count
+    }
+
+    // Clears the restart count and the BIPOP budgets. Only called when a run starts, since
+    // they have to carry over from one restart to the next.
+    pub fn reset(&mut self)
+    {
+        self.count = 0;
+        self.large = 0.0;
+        self.large_regime = true;
+        self.large_evaluations = 0;
+        self.small_evaluations = 0;
+    }
+
+    // Charges objective evaluations to the regime of the current restart
+    pub fn record(&mut self, evaluations: usize)
+    {
+        if self.large_regime
+        {
+            self.large_evaluations += evaluations;
+        }
+        else
+        {
+            self.small_evaluations += evaluations;
+        }
+    }
+
+    pub fn stagnated(&self, discrete_entropies: &[f32], iters_without_improvement: usize) -> bool
+    {
+        let collapsed = discrete_entropies.iter().any(|&entropy| entropy < self.entropy_threshold);
+        self.count < self.max_restarts && (collapsed || iters_without_improvement >= self.patience)
+    }
+
+    // Population of the next restart, given the trainer's base population
+    pub fn restart<R: RandomSource>(&mut self, base: usize, rng: &mut R) -> usize
+    {
+        self.count += 1;
+        if self.large == 0.0
+        {
+            self.large = base as f32;
+        }
+
+        match self.strategy
+        {
+            RestartStrategy::Reset => base,
+            RestartStrategy::Ipop {factor} =>
+            {
+                self.large *= factor;
+                self.large.round() as usize
+            }
+            RestartStrategy::Bipop {factor} =>
+            {
+                self.large_regime = self.small_evaluations >= self.large_evaluations;
+                if self.large_regime
+                {
+                    self.large *= factor;
+                    return self.large.round() as usize;
+                }
+
+                let u = rng.uniform_closed01();
+                let ratio = (0.5 * self.large / base as f32).max(1.0);
+                (base as f32 * ratio.powf(u * u)).floor() as usize
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn one_collapsed_hole_is_stagnation()
+    {
+        let restarts = Restarts::new(RestartStrategy::Reset, 0.1, 50, 3);
+        assert!(restarts.stagnated(&[0.01, 3.0, 2.5], 0));
+        assert!(!restarts.stagnated(&[0.2, 3.0, 2.5], 0));
+        assert!(!restarts.stagnated(&[], 0));
+        assert!(restarts.stagnated(&[], 50));
+    }
+}
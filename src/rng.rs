@@ -1,31 +1,598 @@
-pub struct RNG
-{
-    seed: u32,
-    pos: u32
-}
-
-impl RNG
-{
-    pub fn new(seed: u32) -> Self
-    {
-        Self {seed, pos: 1}
-    }
-
-    pub fn sample(&mut self) -> f32
-    {
-        const NOISE1: u32 = 0x68E31DA4;
-        const NOISE2: u32 = 0xB5297A4D;
-        const NOISE3: u32 = 0x1B56C4E9;
-
-        let mut mangled = self.pos as u32;
-        mangled = mangled.wrapping_mul(NOISE1);
-        mangled = mangled.wrapping_add(self.seed);
-        mangled ^= mangled >> 8;
-        mangled = mangled.wrapping_add(NOISE2);
-        mangled ^= mangled << 8;
-        mangled = mangled.wrapping_mul(NOISE3);
-        mangled ^= mangled >> 8;
-        self.pos += 1;
-        (mangled as f32) / (u32::MAX as f32)
-    }
-}
\ No newline at end of file
+use std::f32::consts::TAU;
+use std::ops::Range;
+
+// Source of random bits, everything else is derived from `next_u32`
+pub trait RandomSource
+{
+    fn next_u32(&mut self) -> u32;
+
+    fn next_u64(&mut self) -> u64
+    {
+        let hi = self.next_u32() as u64;
+        let lo = self.next_u32() as u64;
+        (hi << 32) | lo
+    }
+
+    // Uniform in (0, 1), safe to take the log of. The draw is the midpoint of one of 2^23
+    // equal cells, which f32's 24-bit mantissa represents exactly, so it never rounds to 1
+    fn uniform_open01(&mut self) -> f32
+    {
+        ((self.next_u32() >> 9) as f32 + 0.5) / (1u32 << 23) as f32
+    }
+
+    // Uniform in [0, 1], both ends included
+    fn uniform_closed01(&mut self) -> f32
+    {
+        (self.next_u32() >> 8) as f32 / ((1u32 << 24) - 1) as f32
+    }
+
+    fn uniform_open01_f64(&mut self) -> f64
+    {
+        ((self.next_u64() >> 12) as f64 + 0.5) / (1u64 << 52) as f64
+    }
+
+    fn uniform_closed01_f64(&mut self) -> f64
+    {
+        (self.next_u64() >> 11) as f64 / ((1u64 << 53) - 1) as f64
+    }
+
+    // Uniform in lo..hi, unbiased for integers
+    fn gen_range<T: UniformRange>(&mut self, range: Range<T>) -> T
+    where
+        Self: Sized
+    {
+        T::sample_range(self, range)
+    }
+
+    // Box-Muller transform of two open-interval draws, always finite
+    fn standard_normal(&mut self) -> f32
+    {
+        let u1: f32 = self.uniform_open01();
+        let u2: f32 = self.uniform_open01();
+        (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+    }
+
+    fn standard_normal_f64(&mut self) -> f64
+    {
+        let u1: f64 = self.uniform_open01_f64();
+        let u2: f64 = self.uniform_open01_f64();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
+
+impl<R: RandomSource + ?Sized> RandomSource for &mut R
+{
+    fn next_u32(&mut self) -> u32
+    {
+        (**self).next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64
+    {
+        (**self).next_u64()
+    }
+}
+
+// Uniform in 0..n by Lemire's multiply-and-reject method
+fn below<R: RandomSource + ?Sized>(rng: &mut R, n: u64) -> u64
+{
+    let threshold = n.wrapping_neg() % n;
+    loop
+    {
+        let m = (rng.next_u64() as u128) * (n as u128);
+        if (m as u64) >= threshold
+        {
+            return (m >> 64) as u64;
+        }
+    }
+}
+
+// Types `RandomSource::gen_range` can draw
+pub trait UniformRange: Sized
+{
+    fn sample_range<R: RandomSource + ?Sized>(rng: &mut R, range: Range<Self>) -> Self;
+}
+
+macro_rules! uniform_int_range
+{
+    ($($t:ty),*) =>
+    {
+        $(
+            impl UniformRange for $t
+            {
+                fn sample_range<R: RandomSource + ?Sized>(rng: &mut R, range: Range<Self>) -> Self
+                {
+                    assert!(range.start < range.end, "gen_range called with an empty range");
+                    let span = (range.end as i128 - range.start as i128) as u64;
+                    (range.start as i128 + below(rng, span) as i128) as $t
+                }
+            }
+        )*
+    };
+}
+
+uniform_int_range!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl UniformRange for f32
+{
+    fn sample_range<R: RandomSource + ?Sized>(rng: &mut R, range: Range<Self>) -> Self
+    {
+        assert!(range.start < range.end, "gen_range called with an empty range");
+        assert!(range.start.is_finite() && range.end.is_finite(), "gen_range called with an infinite bound");
+        loop
+        {
+            // Interpolated rather than start + span * u, whose span overflows for wide ranges
+            let u = (rng.next_u32() >> 8) as f32 / (1u32 << 24) as f32;
+            let x = range.start * (1.0 - u) + range.end * u;
+            if range.start <= x && x < range.end
+            {
+                return x;
+            }
+        }
+    }
+}
+
+impl UniformRange for f64
+{
+    fn sample_range<R: RandomSource + ?Sized>(rng: &mut R, range: Range<Self>) -> Self
+    {
+        assert!(range.start < range.end, "gen_range called with an empty range");
+        assert!(range.start.is_finite() && range.end.is_finite(), "gen_range called with an infinite bound");
+        loop
+        {
+            // Interpolated rather than start + span * u, whose span overflows for wide ranges
+            let u = (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+            let x = range.start * (1.0 - u) + range.end * u;
+            if range.start <= x && x < range.end
+            {
+                return x;
+            }
+        }
+    }
+}
+
+// Counter-based generator, draw number `pos` of stream `seed` is a pure hash of the two,
+// so any position of any stream can be reached without generating the ones before it
+#[derive(Clone, Debug)]
+pub struct RNG
+{
+    seed: u32,
+    pos: u32
+}
+
+fn noise(pos: u32, seed: u32) -> u32
+{
+    const NOISE1: u32 = 0x68E31DA4;
+    const NOISE2: u32 = 0xB5297A4D;
+    const NOISE3: u32 = 0x1B56C4E9;
+
+    let mut mangled = pos;
+    mangled = mangled.wrapping_mul(NOISE1);
+    mangled = mangled.wrapping_add(seed);
+    mangled ^= mangled >> 8;
+    mangled = mangled.wrapping_add(NOISE2);
+    mangled ^= mangled << 8;
+    mangled = mangled.wrapping_mul(NOISE3);
+    mangled ^= mangled >> 8;
+
+    // MurmurHash3 finaliser, the hash above leaves the low bits biased and keeps streams
+    // of different seeds correlated
+    mangled ^= mangled >> 16;
+    mangled = mangled.wrapping_mul(0x85EBCA6B);
+    mangled ^= mangled >> 13;
+    mangled = mangled.wrapping_mul(0xC2B2AE35);
+    mangled ^= mangled >> 16;
+    mangled
+}
+
+impl RNG
+{
+    pub fn new(seed: u32) -> Self
+    {
+        Self {seed, pos: 1}
+    }
+
+    pub fn seed(&self) -> u32
+    {
+        self.seed
+    }
+
+    // Counter of the next draw
+    pub fn position(&self) -> u32
+    {
+        self.pos
+    }
+
+    pub fn seek(&mut self, pos: u32)
+    {
+        self.pos = pos;
+    }
+
+    // Independent generator number `id` derived from this generator's seed only, so
+    // worker `id` gets the same stream no matter when or where it is created
+    pub fn stream(&self, id: u32) -> RNG
+    {
+        const STREAM_SALT: u32 = 0x9E3779B9;
+        RNG::new(noise(id ^ STREAM_SALT, noise(STREAM_SALT, self.seed)))
+    }
+
+    // Independent generator derived from this generator's seed, its current position and
+    // `key`, so splitting again after more draws gives a different child. Seeds are 32 bits,
+    // so derived streams start colliding once tens of thousands of them are in use
+    pub fn split(&self, key: u32) -> RNG
+    {
+        const SPLIT_SALT: u32 = 0x85EBCA6B;
+        RNG::new(noise(key ^ SPLIT_SALT, noise(self.pos, self.seed ^ SPLIT_SALT)))
+    }
+}
+
+impl RandomSource for RNG
+{
+    fn next_u32(&mut self) -> u32
+    {
+        let mangled = noise(self.pos, self.seed);
+        self.pos = self.pos.wrapping_add(1);
+        mangled
+    }
+}
+
+// PCG-XSH-RR with 64-bit state and 32-bit output
+#[derive(Clone, Debug)]
+pub struct Pcg32
+{
+    state: u64,
+    inc: u64
+}
+
+impl Pcg32
+{
+    const MULTIPLIER: u64 = 6364136223846793005;
+
+    pub fn new(seed: u64, stream: u64) -> Self
+    {
+        let mut rng = Self {state: 0, inc: (stream << 1) | 1};
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+}
+
+impl RandomSource for Pcg32
+{
+    fn next_u32(&mut self) -> u32
+    {
+        let old = self.state;
+        self.state = old.wrapping_mul(Self::MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+}
+
+// xoshiro256++, seeded through splitmix64 so that any seed gives a non-zero state
+#[derive(Clone, Debug)]
+pub struct Xoshiro256PlusPlus
+{
+    s: [u64; 4]
+}
+
+impl Xoshiro256PlusPlus
+{
+    pub fn new(seed: u64) -> Self
+    {
+        let mut x = seed;
+        let mut splitmix = || {
+            x = x.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^ (z >> 31)
+        };
+
+        Self {s: [splitmix(), splitmix(), splitmix(), splitmix()]}
+    }
+}
+
+impl RandomSource for Xoshiro256PlusPlus
+{
+    fn next_u32(&mut self) -> u32
+    {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64
+    {
+        let s = &mut self.s;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+}
+
+// Wraps a source and keeps every 32-bit word it hands out, for later `Replay`
+pub struct Recorder<R>
+{
+    pub inner: R,
+    pub words: Vec<u32>
+}
+
+impl<R: RandomSource> Recorder<R>
+{
+    pub fn new(inner: R) -> Self
+    {
+        Self {inner, words: Vec::new()}
+    }
+
+    pub fn replay(&self) -> Replay
+    {
+        Replay::new(self.words.clone())
+    }
+}
+
+impl<R: RandomSource> RandomSource for Recorder<R>
+{
+    fn next_u32(&mut self) -> u32
+    {
+        let word = self.inner.next_u32();
+        self.words.push(word);
+        word
+    }
+}
+
+// Plays back a recorded stream of 32-bit words, panics once it runs out
+pub struct Replay
+{
+    words: Vec<u32>,
+    pos: usize
+}
+
+impl Replay
+{
+    pub fn new(words: Vec<u32>) -> Self
+    {
+        Self {words, pos: 0}
+    }
+
+    pub fn remaining(&self) -> usize
+    {
+        self.words.len() - self.pos
+    }
+}
+
+impl RandomSource for Replay
+{
+    fn next_u32(&mut self) -> u32
+    {
+        let word = *self.words.get(self.pos).expect("replayed random stream exhausted");
+        self.pos += 1;
+        word
+    }
+}
+
+// Exposes any `RandomSource` as a `rand_core::RngCore`, e.g. `RandCore(RNG::new(0))`
+pub struct RandCore<R>(pub R);
+
+impl<R: RandomSource> rand_core::RngCore for RandCore<R>
+{
+    fn next_u32(&mut self) -> u32
+    {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64
+    {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8])
+    {
+        rand_core::impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::stats;
+
+    const ALPHA: f64 = 1e-4;
+
+    fn check(name: &str, p_value: f64)
+    {
+        println!("{}: p-value = {:.6}", name, p_value);
+        assert!(p_value > ALPHA, "{} failed with p-value {}", name, p_value);
+    }
+
+    // Counts of 256 byte values taken from bits `shift..shift + 8` of each word
+    fn byte_uniformity<R: RandomSource>(rng: &mut R, shift: u32) -> f64
+    {
+        const NUM_SAMPLES: usize = 1 << 20;
+        let mut observed = vec![0u64; 256];
+        for _ in 0..NUM_SAMPLES
+        {
+            observed[((rng.next_u32() >> shift) & 0xFF) as usize] += 1;
+        }
+
+        let expected = vec![NUM_SAMPLES as f64 / 256.0; 256];
+        stats::chi_square_test(&observed, &expected)
+    }
+
+    fn correlation(xs: &[f64], ys: &[f64]) -> f64
+    {
+        let n = xs.len() as f64;
+        let mean_x = xs.iter().sum::<f64>() / n;
+        let mean_y = ys.iter().sum::<f64>() / n;
+
+        let mut cov = 0.0;
+        let mut var_x = 0.0;
+        let mut var_y = 0.0;
+        for (x, y) in xs.iter().zip(ys.iter())
+        {
+            cov += (x - mean_x) * (y - mean_y);
+            var_x += (x - mean_x).powi(2);
+            var_y += (y - mean_y).powi(2);
+        }
+
+        cov / (var_x * var_y).sqrt()
+    }
+
+    // Under independence sqrt(n) * r is approximately standard normal
+    fn correlation_p_value(xs: &[f64], ys: &[f64]) -> f64
+    {
+        stats::normal_two_sided(correlation(xs, ys) * (xs.len() as f64).sqrt())
+    }
+
+    fn serial_correlation<R: RandomSource>(rng: &mut R) -> f64
+    {
+        let xs: Vec<f64> = (0..1 << 20).map(|_| rng.uniform_closed01_f64()).collect();
+        correlation_p_value(&xs[..xs.len() - 1], &xs[1..])
+    }
+
+    // Wald-Wolfowitz runs above and below 1/2
+    fn runs<R: RandomSource>(rng: &mut R) -> f64
+    {
+        let above: Vec<bool> = (0..1 << 20).map(|_| rng.next_u32() >= 1 << 31).collect();
+        let n1 = above.iter().filter(|&&a| a).count() as f64;
+        let n2 = above.len() as f64 - n1;
+        let n = n1 + n2;
+        let runs = 1 + above.windows(2).filter(|w| w[0] != w[1]).count();
+
+        let mean = 2.0 * n1 * n2 / n + 1.0;
+        let var = (mean - 1.0) * (mean - 2.0) / (n - 1.0);
+        stats::normal_two_sided((runs as f64 - mean) / var.sqrt())
+    }
+
+    // Marsaglia's birthday spacings: 512 birthdays in a year of 2^24 days, the number of
+    // repeated spacings is Poisson with mean 512^3 / (4 * 2^24) = 2
+    fn birthday_spacings<R: RandomSource>(rng: &mut R) -> f64
+    {
+        const NUM_TRIALS: usize = 2000;
+        const MAX_BIN: usize = 7;
+        let lambda = 2.0;
+
+        let mut observed = vec![0u64; MAX_BIN + 1];
+        for _ in 0..NUM_TRIALS
+        {
+            let mut days: Vec<u32> = (0..512).map(|_| rng.next_u32() >> 8).collect();
+            days.sort_unstable();
+            let mut spacings: Vec<u32> = days.windows(2).map(|w| w[1] - w[0]).collect();
+            spacings.sort_unstable();
+            let repeats = spacings.windows(2).filter(|w| w[0] == w[1]).count();
+            observed[repeats.min(MAX_BIN)] += 1;
+        }
+
+        let mut expected: Vec<f64> = (0..MAX_BIN as u64)
+            .map(|k| stats::poisson_pmf(k, lambda) * NUM_TRIALS as f64)
+            .collect();
+        expected.push(NUM_TRIALS as f64 - expected.iter().sum::<f64>());
+
+        stats::chi_square_test(&observed, &expected)
+    }
+
+    fn battery<R: RandomSource, F: Fn() -> R>(name: &str, make: F)
+    {
+        check(&format!("{} uniformity of bits 24..32", name), byte_uniformity(&mut make(), 24));
+        check(&format!("{} uniformity of bits 0..8", name), byte_uniformity(&mut make(), 0));
+        check(&format!("{} serial correlation", name), serial_correlation(&mut make()));
+        check(&format!("{} runs", name), runs(&mut make()));
+        check(&format!("{} birthday spacings", name), birthday_spacings(&mut make()));
+    }
+
+    #[test]
+    fn squirrel_battery()
+    {
+        battery("RNG(0)", || RNG::new(0));
+        battery("RNG(10)", || RNG::new(10));
+    }
+
+    #[test]
+    fn pcg32_battery()
+    {
+        battery("Pcg32", || Pcg32::new(42, 54));
+    }
+
+    #[test]
+    fn xoshiro_battery()
+    {
+        battery("Xoshiro256PlusPlus", || Xoshiro256PlusPlus::new(7));
+    }
+
+    fn seed_correlation(mut a: RNG, mut b: RNG) -> f64
+    {
+        const NUM_SAMPLES: usize = 1 << 18;
+        let xs: Vec<f64> = (0..NUM_SAMPLES).map(|_| a.uniform_closed01_f64()).collect();
+        let ys: Vec<f64> = (0..NUM_SAMPLES).map(|_| b.uniform_closed01_f64()).collect();
+        correlation_p_value(&xs, &ys)
+    }
+
+    // Fraction of equal bits between words drawn in lockstep is Binomial(32 n, 1/2)
+    fn seed_bit_agreement(mut a: RNG, mut b: RNG) -> f64
+    {
+        const NUM_SAMPLES: usize = 1 << 18;
+        let mut agree = 0u64;
+        for _ in 0..NUM_SAMPLES
+        {
+            agree += (!(a.next_u32() ^ b.next_u32())).count_ones() as u64;
+        }
+
+        let bits = 32.0 * NUM_SAMPLES as f64;
+        stats::normal_two_sided((agree as f64 - 0.5 * bits) / (0.25 * bits).sqrt())
+    }
+
+    #[test]
+    fn adjacent_seeds_are_uncorrelated()
+    {
+        for (s1, s2) in [(0, 1), (9, 10), (10, 11), (0, 10), (u32::MAX, 0)]
+        {
+            check(&format!("correlation of seeds {} and {}", s1, s2), seed_correlation(RNG::new(s1), RNG::new(s2)));
+            check(&format!("bit agreement of seeds {} and {}", s1, s2), seed_bit_agreement(RNG::new(s1), RNG::new(s2)));
+        }
+    }
+
+    #[test]
+    fn derived_streams_are_uncorrelated()
+    {
+        let root = RNG::new(0);
+        check("correlation of streams 0 and 1", seed_correlation(root.stream(0), root.stream(1)));
+        check("bit agreement of streams 0 and 1", seed_bit_agreement(root.stream(0), root.stream(1)));
+        check("correlation of root and split", seed_correlation(root.clone(), root.split(0)));
+        check("bit agreement of root and split", seed_bit_agreement(root.clone(), root.split(0)));
+        battery("RNG(0).stream(3)", || root.stream(3));
+    }
+
+    #[test]
+    fn float_ranges_wider_than_the_type_terminate()
+    {
+        let mut rng = RNG::new(4);
+        let wide: Vec<f32> = (0..1000).map(|_| rng.gen_range(f32::MIN..f32::MAX)).collect();
+        assert!(wide.iter().all(|x| x.is_finite()));
+        assert!(wide.iter().any(|&x| x < 0.0) && wide.iter().any(|&x| x > 0.0));
+
+        let wide: Vec<f64> = (0..1000).map(|_| rng.gen_range(f64::MIN..f64::MAX)).collect();
+        assert!(wide.iter().all(|x| x.is_finite()));
+        assert!(wide.iter().any(|&x| x < 0.0) && wide.iter().any(|&x| x > 0.0));
+
+        for _ in 0..1000
+        {
+            let x = rng.gen_range(-2.5f32..-1.0);
+            assert!((-2.5..-1.0).contains(&x), "{} outside [-2.5, -1)", x);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "infinite bound")]
+    fn float_ranges_reject_infinite_bounds()
+    {
+        RNG::new(0).gen_range(0.0..f32::INFINITY);
+    }
+}
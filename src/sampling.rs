@@ -0,0 +1,356 @@
+use crate::rng::RandomSource;
+
+// How the members of a population are drawn from the holes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sampling
+{
+    // Every member drawn on its own with `Distribution::sample`
+    Independent,
+    // Members drawn in pairs at u and 1 - u, which mirrors a normal around its mean
+    Antithetic,
+    // Latin hypercube, every coordinate puts exactly one member in each of n equal strata
+    Stratified,
+    // Halton sequence under a random shift
+    Halton,
+    // Sobol sequence under a random shift, continued by Halton coordinates past the
+    // dimensions of the Sobol direction table
+    Sobol
+}
+
+impl Sampling
+{
+    // `n` points of the unit cube of dimension `dim`, every coordinate strictly inside (0, 1)
+    pub fn points<R: RandomSource>(&self, n: usize, dim: usize, rng: &mut R) -> Vec<Vec<f32>>
+    {
+        match self
+        {
+            Sampling::Independent =>
+            {
+                (0..n).map(|_| (0..dim).map(|_| rng.uniform_open01()).collect()).collect()
+            }
+            Sampling::Antithetic =>
+            {
+                let mut points: Vec<Vec<f32>> = Vec::with_capacity(n);
+                while points.len() < n
+                {
+                    let u: Vec<f32> = (0..dim).map(|_| rng.uniform_open01()).collect();
+                    let mirrored: Vec<f32> = u.iter().map(|u| 1.0 - u).collect();
+                    points.push(u);
+                    if points.len() < n
+                    {
+                        points.push(mirrored);
+                    }
+                }
+                points
+            }
+            Sampling::Stratified =>
+            {
+                let mut points = vec![vec![0.0; dim]; n];
+                for j in 0..dim
+                {
+                    // Fisher-Yates shuffle of the strata
+                    let mut strata: Vec<usize> = (0..n).collect();
+                    for i in (1..n).rev()
+                    {
+                        strata.swap(i, rng.gen_range(0..i + 1));
+                    }
+
+                    for (point, stratum) in points.iter_mut().zip(strata)
+                    {
+                        point[j] = open((stratum as f64 + rng.uniform_open01_f64()) / n as f64);
+                    }
+                }
+                points
+            }
+            Sampling::Halton => shifted(n, dim, rng, halton),
+            Sampling::Sobol => shifted(n, dim, rng, |i, j| if j < SOBOL_DIMS {sobol(i, j)} else {halton(i, j)})
+        }
+    }
+}
+
+// Cranley-Patterson rotation, every point moved by the same uniform shift modulo 1 so each
+// point is marginally uniform while the whole set keeps its low discrepancy
+fn shifted<R, F>(n: usize, dim: usize, rng: &mut R, sequence: F) -> Vec<Vec<f32>>
+where
+    R: RandomSource,
+    F: Fn(usize, usize) -> f64
+{
+    let shift: Vec<f64> = (0..dim).map(|_| rng.uniform_open01_f64()).collect();
+    (0..n)
+        .map(|i| (0..dim).map(|j| open((sequence(i, j) + shift[j]).fract())).collect())
+        .collect()
+}
+
+// Rounds to f32 while staying strictly inside (0, 1)
+fn open(u: f64) -> f32
+{
+    const EDGE: f32 = 1.0 / (1u32 << 24) as f32;
+    (u as f32).clamp(EDGE, 1.0 - EDGE)
+}
+
+// Coordinate `j` of Halton point `i`, the radical inverse of i in the j-th prime base
+fn halton(i: usize, j: usize) -> f64
+{
+    let base = nth_prime(j) as f64;
+    let mut index = i as f64;
+    let mut scale = 1.0;
+    let mut value = 0.0;
+    while index > 0.0
+    {
+        scale /= base;
+        value += scale * (index % base);
+        index = (index / base).floor();
+    }
+    value
+}
+
+fn nth_prime(n: usize) -> usize
+{
+    let mut count = 0;
+    let mut candidate = 1;
+    while count <= n
+    {
+        candidate += 1;
+        if (2..candidate).take_while(|d| d * d <= candidate).all(|d| candidate % d != 0)
+        {
+            count += 1;
+        }
+    }
+    candidate
+}
+
+// Primitive polynomial degree s, its interior coefficients a and the initial direction
+// numbers m of Sobol dimensions 2 and up, from Joe and Kuo's new-joe-kuo-6.21201 table
+const SOBOL_TABLE: [(u32, u32, &[u32]); 20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69])
+];
+
+// Dimension 1 needs no table entry. Halton coordinate j of a later dimension has base
+// nth_prime(j) >= 79, so it never repeats the base 2 of Sobol dimension 1.
+const SOBOL_DIMS: usize = SOBOL_TABLE.len() + 1;
+
+// Direction numbers of Sobol dimension `j`, scaled to 32 bits
+fn sobol_directions(j: usize) -> [u32; 32]
+{
+    let mut v = [0u32; 32];
+    if j == 0
+    {
+        for (k, v) in v.iter_mut().enumerate()
+        {
+            *v = 1 << (31 - k);
+        }
+        return v;
+    }
+
+    assert!(j < SOBOL_DIMS, "Sobol direction numbers are tabulated for {} dimensions", SOBOL_DIMS);
+    let (s, a, m) = SOBOL_TABLE[j - 1];
+    let s = s as usize;
+
+    for k in 0..32
+    {
+        if k < s
+        {
+            v[k] = m[k] << (31 - k);
+            continue;
+        }
+
+        v[k] = v[k - s] ^ (v[k - s] >> s);
+        for l in 1..s
+        {
+            if (a >> (s - 1 - l)) & 1 == 1
+            {
+                v[k] ^= v[k - l];
+            }
+        }
+    }
+
+    v
+}
+
+// Coordinate `j` of Sobol point `i`
+fn sobol(i: usize, j: usize) -> f64
+{
+    let v = sobol_directions(j);
+    let mut x: u32 = 0;
+    for (k, v) in v.iter().enumerate()
+    {
+        if (i >> k) & 1 == 1
+        {
+            x ^= v;
+        }
+    }
+    x as f64 / (1u64 << 32) as f64
+}
+
+// Inverse of the standard normal CDF by Acklam's rational approximation, relative error
+// below 1.2e-9 for p in (0, 1)
+pub fn normal_quantile(p: f64) -> f64
+{
+    const A: [f64; 6] = [
+        -3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2,
+        1.38357751867269e2, -3.066479806614716e1, 2.506628277459239
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2,
+        6.680131188771972e1, -1.328068155288572e1
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838,
+        -2.549732539343734, 4.374664141464968, 2.938163982698783
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996, 3.754408661907416
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < P_LOW
+    {
+        return tail((-2.0 * p.ln()).sqrt());
+    }
+
+    if p > 1.0 - P_LOW
+    {
+        return -tail((-2.0 * (1.0 - p).ln()).sqrt());
+    }
+
+    let q = p - 0.5;
+    let r = q * q;
+    (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+        / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::dist::Normal;
+    use crate::hole::HoleSet;
+    use crate::rng::RNG;
+
+    #[test]
+    fn sobol_continues_with_halton_past_its_table()
+    {
+        let dim = SOBOL_DIMS + 10;
+        let points = Sampling::Sobol.points(64, dim, &mut RNG::new(5));
+        assert!(points.iter().flatten().all(|&u| u > 0.0 && u < 1.0));
+
+        // Extra coordinates are neither a copy nor a shift of an earlier one
+        let column = |j: usize| -> Vec<f32> { points.iter().map(|point| point[j]).collect() };
+        for j in SOBOL_DIMS..dim
+        {
+            let extra = column(j);
+            for k in 0..j
+            {
+                let offsets: Vec<f32> = column(k).iter().zip(extra.iter()).map(|(a, b)| (a - b).rem_euclid(1.0)).collect();
+                assert!(offsets.iter().any(|offset| (offset - offsets[0]).abs() > 1e-3), "coordinate {} shifts coordinate {}", j, k);
+            }
+        }
+    }
+
+    #[test]
+    fn antithetic_points_come_in_mirrored_pairs()
+    {
+        let points = Sampling::Antithetic.points(7, 3, &mut RNG::new(2));
+        assert_eq!(points.len(), 7);
+        for pair in points.chunks_exact(2)
+        {
+            for (u, mirrored) in pair[0].iter().zip(pair[1].iter())
+            {
+                assert_eq!(*mirrored, 1.0 - u);
+            }
+        }
+    }
+
+    #[test]
+    fn antithetic_normals_mirror_around_the_mean()
+    {
+        let mut holes = HoleSet::new();
+        let id = holes.add(Normal::new(1.5, 0.4));
+        let population = holes.sample_population(20, Sampling::Antithetic, &mut RNG::new(3));
+        for pair in population.chunks_exact(2)
+        {
+            let (x, mirrored) = (pair[0][id], pair[1][id]);
+            assert!((mirrored - (2.0 * 1.5 - x)).abs() < 1e-5, "{} and {}", x, mirrored);
+        }
+    }
+
+    #[test]
+    fn stratified_points_fill_every_stratum_once()
+    {
+        for n in [1, 2, 7, 64]
+        {
+            let points = Sampling::Stratified.points(n, 4, &mut RNG::new(n as u32));
+            for j in 0..4
+            {
+                let mut strata: Vec<usize> = points.iter().map(|point| (point[j] * n as f32) as usize).collect();
+                strata.sort_unstable();
+                assert_eq!(strata, (0..n).collect::<Vec<_>>(), "n = {}, coordinate {}", n, j);
+            }
+        }
+    }
+
+    #[test]
+    fn halton_starts_with_the_radical_inverses()
+    {
+        let expected: [&[f64]; 3] = [
+            &[0.0, 0.5, 0.25, 0.75, 0.125, 0.625],
+            &[0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0, 7.0 / 9.0],
+            &[0.0, 0.2, 0.4, 0.6, 0.8, 0.04]
+        ];
+        for (j, values) in expected.iter().enumerate()
+        {
+            for (i, &value) in values.iter().enumerate()
+            {
+                assert!((halton(i, j) - value).abs() < 1e-12, "point {}, coordinate {}: {}", i, j, halton(i, j));
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_starts_with_the_published_points()
+    {
+        // First eight points of dimensions 1 and 2 of Joe and Kuo's generator, which lists
+        // them in Gray code order, so only the sets are compared
+        let published = [
+            (0.0, 0.0), (0.5, 0.5), (0.75, 0.25), (0.25, 0.75),
+            (0.375, 0.375), (0.875, 0.875), (0.625, 0.125), (0.125, 0.625)
+        ];
+        let mut points: Vec<(f64, f64)> = (0..8).map(|i| (sobol(i, 0), sobol(i, 1))).collect();
+        let mut published = published.to_vec();
+        points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        published.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(points, published);
+
+        // Every coordinate puts one of the first 2^k points in each of 2^k strata
+        for j in 0..SOBOL_DIMS
+        {
+            let mut strata: Vec<usize> = (0..32).map(|i| (sobol(i, j) * 32.0) as usize).collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..32).collect::<Vec<_>>(), "coordinate {}", j);
+        }
+    }
+}
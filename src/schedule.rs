@@ -0,0 +1,345 @@
+use std::f32::consts::PI;
+
+// Learning rate of every iteration, scaled from the trainer's base rate. `observe` gets the
+// mean loss of each finished iteration for schedules that react to progress.
+pub trait RateSchedule
+{
+    fn rate(&self, iter: usize, base: f32) -> f32;
+
+    fn observe(&mut self, _loss: f32) {}
+
+    // Forgets the losses `observe` has seen. Called when a run starts and after each restart,
+    // from which `rate` then counts iterations again.
+    fn reset(&mut self) {}
+}
+
+// The base rate throughout
+pub struct ConstantRate;
+
+impl RateSchedule for ConstantRate
+{
+    fn rate(&self, _iter: usize, base: f32) -> f32
+    {
+        base
+    }
+}
+
+// Multiplies the rate by `factor` every `every` iterations
+pub struct StepDecay
+{
+    pub every: usize,
+    pub factor: f32
+}
+
+impl StepDecay
+{
+    pub fn new(every: usize, factor: f32) -> Self
+    {
+        Self {every, factor}
+    }
+}
+
+impl RateSchedule for StepDecay
+{
+    fn rate(&self, iter: usize, base: f32) -> f32
+    {
+        base * self.factor.powi((iter / self.every.max(1)) as i32)
+    }
+}
+
+// Half a cosine from the base rate down to `min_rate` over `iters` iterations, then flat
+pub struct CosineDecay
+{
+    pub iters: usize,
+    pub min_rate: f32
+}
+
+impl CosineDecay
+{
+    pub fn new(iters: usize, min_rate: f32) -> Self
+    {
+        Self {iters, min_rate}
+    }
+}
+
+impl RateSchedule for CosineDecay
+{
+    fn rate(&self, iter: usize, base: f32) -> f32
+    {
+        let progress = iter.min(self.iters) as f32 / self.iters.max(1) as f32;
+        self.min_rate + 0.5 * (base - self.min_rate) * (1.0 + (PI * progress).cos())
+    }
+}
+
+// base * decay^iter
+pub struct ExponentialDecay
+{
+    pub decay: f32
+}
+
+impl ExponentialDecay
+{
+    pub fn new(decay: f32) -> Self
+    {
+        Self {decay}
+    }
+}
+
+impl RateSchedule for ExponentialDecay
+{
+    fn rate(&self, iter: usize, base: f32) -> f32
+    {
+        base * self.decay.powi(iter as i32)
+    }
+}
+
+// Ramps linearly up to `inner` over the first `iters` iterations
+pub struct Warmup<S>
+{
+    pub iters: usize,
+    pub inner: S
+}
+
+impl<S: RateSchedule> Warmup<S>
+{
+    pub fn new(iters: usize, inner: S) -> Self
+    {
+        Self {iters, inner}
+    }
+}
+
+impl<S: RateSchedule> RateSchedule for Warmup<S>
+{
+    fn rate(&self, iter: usize, base: f32) -> f32
+    {
+        let ramp = ((iter + 1) as f32 / self.iters.max(1) as f32).min(1.0);
+        ramp * self.inner.rate(iter, base)
+    }
+
+    fn observe(&mut self, loss: f32)
+    {
+        self.inner.observe(loss);
+    }
+
+    fn reset(&mut self)
+    {
+        self.inner.reset();
+    }
+}
+
+// Multiplies the rate by `factor` whenever the loss has not improved on its best by a
+// relative `threshold` for more than `patience` iterations, never going below `min_rate`
+pub struct Plateau
+{
+    pub factor: f32,
+    pub patience: usize,
+    pub threshold: f32,
+    pub min_rate: f32,
+    best: f32,
+    wait: usize,
+    scale: f32
+}
+
+impl Plateau
+{
+    pub fn new(factor: f32, patience: usize, threshold: f32, min_rate: f32) -> Self
+    {
+        Self {factor, patience, threshold, min_rate, best: f32::INFINITY, wait: 0, scale: 1.0}
+    }
+}
+
+impl RateSchedule for Plateau
+{
+    fn rate(&self, _iter: usize, base: f32) -> f32
+    {
+        (base * self.scale).max(self.min_rate)
+    }
+
+    fn observe(&mut self, loss: f32)
+    {
+        if self.best == f32::INFINITY || loss < self.best - self.threshold * self.best.abs()
+        {
+            self.best = loss;
+            self.wait = 0;
+            return;
+        }
+
+        self.wait += 1;
+        if self.wait > self.patience
+        {
+            self.scale *= self.factor;
+            self.wait = 0;
+        }
+    }
+
+    fn reset(&mut self)
+    {
+        self.best = f32::INFINITY;
+        self.wait = 0;
+        self.scale = 1.0;
+    }
+}
+
+// Number of mutations sampled at every iteration, grown from the trainer's base size
+pub trait PopulationSchedule
+{
+    fn size(&self, iter: usize, base: usize) -> usize;
+}
+
+// The base size throughout
+pub struct FixedPopulation;
+
+impl PopulationSchedule for FixedPopulation
+{
+    fn size(&self, _iter: usize, base: usize) -> usize
+    {
+        base
+    }
+}
+
+// Grows linearly from the base size to `max_size` over `iters` iterations
+pub struct LinearGrowth
+{
+    pub iters: usize,
+    pub max_size: usize
+}
+
+impl LinearGrowth
+{
+    pub fn new(iters: usize, max_size: usize) -> Self
+    {
+        Self {iters, max_size}
+    }
+}
+
+impl PopulationSchedule for LinearGrowth
+{
+    fn size(&self, iter: usize, base: usize) -> usize
+    {
+        let progress = iter.min(self.iters) as f32 / self.iters.max(1) as f32;
+        base + (progress * self.max_size.saturating_sub(base) as f32) as usize
+    }
+}
+
+// Doubles the base size every `every` iterations up to `max_size`
+pub struct Doubling
+{
+    pub every: usize,
+    pub max_size: usize
+}
+
+impl Doubling
+{
+    pub fn new(every: usize, max_size: usize) -> Self
+    {
+        Self {every, max_size}
+    }
+}
+
+impl PopulationSchedule for Doubling
+{
+    fn size(&self, iter: usize, base: usize) -> usize
+    {
+        let doublings = (iter / self.every.max(1)).min(usize::BITS as usize - 1) as u32;
+        base.saturating_mul(1 << doublings).min(self.max_size.max(base))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32)
+    {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn decays_start_at_the_base_rate()
+    {
+        assert_close(StepDecay::new(10, 0.5).rate(9, 2.0), 2.0);
+        assert_close(StepDecay::new(10, 0.5).rate(25, 2.0), 0.5);
+
+        let cosine = CosineDecay::new(100, 0.1);
+        assert_close(cosine.rate(0, 1.0), 1.0);
+        assert_close(cosine.rate(50, 1.0), 0.55);
+        assert_close(cosine.rate(100, 1.0), 0.1);
+        assert_close(cosine.rate(1000, 1.0), 0.1);
+
+        assert_close(ExponentialDecay::new(0.5).rate(3, 2.0), 0.25);
+    }
+
+    #[test]
+    fn warmup_ramps_up_to_the_inner_schedule()
+    {
+        let warmup = Warmup::new(4, StepDecay::new(6, 0.5));
+        let rates: Vec<f32> = (0..8).map(|iter| warmup.rate(iter, 2.0)).collect();
+        assert_eq!(rates, vec![0.5, 1.0, 1.5, 2.0, 2.0, 2.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn plateau_decays_after_its_patience_and_resets()
+    {
+        let mut plateau = Plateau::new(0.5, 2, 0.1, 0.2);
+        plateau.observe(1.0);
+
+        // Not better than the best by 10%, so these count against the patience
+        for _ in 0..2
+        {
+            plateau.observe(0.95);
+            assert_close(plateau.rate(0, 1.0), 1.0);
+        }
+        plateau.observe(0.95);
+        assert_close(plateau.rate(0, 1.0), 0.5);
+
+        // An improvement restarts the wait
+        plateau.observe(0.5);
+        plateau.observe(0.5);
+        plateau.observe(0.5);
+        assert_close(plateau.rate(0, 1.0), 0.5);
+        plateau.observe(0.5);
+        assert_close(plateau.rate(0, 1.0), 0.25);
+
+        // Floored at the minimum rate
+        for _ in 0..3
+        {
+            plateau.observe(0.5);
+        }
+        assert_close(plateau.rate(0, 1.0), 0.2);
+
+        plateau.reset();
+        assert_close(plateau.rate(0, 1.0), 1.0);
+        plateau.observe(10.0);
+        plateau.observe(10.0);
+        assert_close(plateau.rate(0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn warmup_forwards_observations_and_resets()
+    {
+        let mut warmup = Warmup::new(1, Plateau::new(0.5, 0, 0.0, 0.0));
+        warmup.observe(1.0);
+        warmup.observe(1.0);
+        assert_close(warmup.rate(5, 1.0), 0.5);
+        warmup.reset();
+        assert_close(warmup.rate(5, 1.0), 1.0);
+    }
+
+    #[test]
+    fn population_growth_is_clamped()
+    {
+        let linear = LinearGrowth::new(10, 30);
+        let sizes: Vec<usize> = [0, 5, 10, 100].iter().map(|&iter| linear.size(iter, 10)).collect();
+        assert_eq!(sizes, vec![10, 20, 30, 30]);
+        assert_eq!(LinearGrowth::new(10, 5).size(10, 8), 8);
+
+        let doubling = Doubling::new(2, 20);
+        let sizes: Vec<usize> = [0, 1, 2, 4, 6, 10_000].iter().map(|&iter| doubling.size(iter, 3)).collect();
+        assert_eq!(sizes, vec![3, 3, 6, 12, 20, 20]);
+        assert_eq!(Doubling::new(2, 5).size(100, 8), 8);
+        assert_eq!(Doubling::new(0, usize::MAX).size(usize::MAX, 3), usize::MAX);
+
+        assert_eq!(FixedPopulation.size(1000, 7), 7);
+    }
+}
@@ -0,0 +1,248 @@
+// Fitness shaping, turns the raw losses of a population into the scores the gradient
+// estimators descend on. Lower is better for both.
+pub trait FitnessShaper
+{
+    fn shape(&self, losses: &[f32]) -> Vec<f32>;
+}
+
+// (loss - mean) / stddev, with the stddev floored at `epsilon` so a population of equal
+// losses gives zero scores instead of NaN. The mean and stddev are over the finite losses,
+// and NaN or infinite losses get the worst score of the finite ones, so one division by
+// zero does not turn the whole population into NaN.
+pub struct ZScore
+{
+    pub epsilon: f32
+}
+
+impl ZScore
+{
+    pub fn new(epsilon: f32) -> Self
+    {
+        Self {epsilon}
+    }
+}
+
+impl Default for ZScore
+{
+    fn default() -> Self
+    {
+        Self::new(1e-8)
+    }
+}
+
+impl FitnessShaper for ZScore
+{
+    fn shape(&self, losses: &[f32]) -> Vec<f32>
+    {
+        let mut mean = 0.0;
+        let mut var = 0.0;
+        let mut count = 0;
+        let mut worst = f32::NEG_INFINITY;
+
+        for &loss in losses.iter().filter(|loss| loss.is_finite())
+        {
+            count += 1;
+            let old_mean = mean;
+            mean += (loss - mean) / count as f32;
+            var += (loss - mean) * (loss - old_mean);
+            worst = worst.max(loss);
+        }
+
+        let stddev = if count > 1 {(var / (count as f32 - 1.0)).sqrt()} else {0.0};
+        let stddev = stddev.max(self.epsilon);
+        let worst = if count > 0 {(worst - mean) / stddev} else {0.0};
+        losses.iter()
+            .map(|loss| if loss.is_finite() {(loss - mean) / stddev} else {worst})
+            .collect()
+    }
+}
+
+// The rank utilities of NES, max(0, ln(n / 2 + 1) - ln(rank)) normalised to sum to one and
+// shifted by -1 / n, negated so that the best half gets negative scores
+pub struct RankUtilities;
+
+impl FitnessShaper for RankUtilities
+{
+    fn shape(&self, losses: &[f32]) -> Vec<f32>
+    {
+        let n = losses.len() as f32;
+        let raw = |position: usize| ((0.5 * n + 1.0).ln() - (position as f32 + 1.0).ln()).max(0.0);
+        let total: f32 = (0..losses.len()).map(raw).sum();
+
+        by_rank(losses, |position| 1.0 / n - raw(position) / total)
+    }
+}
+
+// Ranks scaled into [-0.5, 0.5], the best loss gets -0.5 and the worst 0.5
+pub struct CenteredRank;
+
+impl FitnessShaper for CenteredRank
+{
+    fn shape(&self, losses: &[f32]) -> Vec<f32>
+    {
+        if losses.len() < 2
+        {
+            return vec![0.0; losses.len()];
+        }
+
+        let last = (losses.len() - 1) as f32;
+        by_rank(losses, |position| position as f32 / last - 0.5)
+    }
+}
+
+// Shapes ln(1 + loss) instead of the loss, which compresses the huge losses a division by
+// a near-zero value produces. Losses must be non-negative.
+pub struct LogLoss<S>
+{
+    pub inner: S
+}
+
+impl<S: FitnessShaper> LogLoss<S>
+{
+    pub fn new(inner: S) -> Self
+    {
+        Self {inner}
+    }
+}
+
+impl<S: FitnessShaper> FitnessShaper for LogLoss<S>
+{
+    fn shape(&self, losses: &[f32]) -> Vec<f32>
+    {
+        let logs: Vec<f32> = losses.iter().map(|loss| loss.ln_1p()).collect();
+        self.inner.shape(&logs)
+    }
+}
+
+// `value` of each loss's position in ascending order, averaged over runs of tied losses so
+// that equal losses always get equal scores
+fn by_rank<F: Fn(usize) -> f32>(losses: &[f32], value: F) -> Vec<f32>
+{
+    let mut order: Vec<usize> = (0..losses.len()).collect();
+    order.sort_by(|&a, &b| losses[a].total_cmp(&losses[b]));
+
+    let mut shaped = vec![0.0; losses.len()];
+    let mut start = 0;
+    while start < order.len()
+    {
+        let mut end = start + 1;
+        while end < order.len() && losses[order[end]] == losses[order[start]]
+        {
+            end += 1;
+        }
+
+        let mean = (start..end).map(&value).sum::<f32>() / (end - start) as f32;
+        for &i in order[start..end].iter()
+        {
+            shaped[i] = mean;
+        }
+        start = end;
+    }
+
+    shaped
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn shapers() -> Vec<(&'static str, Box<dyn FitnessShaper>)>
+    {
+        vec![
+            ("z-score", Box::new(ZScore::default())),
+            ("rank utilities", Box::new(RankUtilities)),
+            ("centered rank", Box::new(CenteredRank)),
+            ("log z-score", Box::new(LogLoss::new(ZScore::default()))),
+            ("log rank utilities", Box::new(LogLoss::new(RankUtilities)))
+        ]
+    }
+
+    #[test]
+    fn equal_losses_give_zero_scores()
+    {
+        for (name, shaper) in shapers()
+        {
+            for losses in [vec![3.5; 50], vec![0.0; 50], vec![1e6; 2]]
+            {
+                let scores = shaper.shape(&losses);
+                assert_eq!(scores.len(), losses.len());
+                assert!(scores.iter().all(|s| s.abs() < 1e-6), "{}: {:?}", name, scores);
+            }
+        }
+    }
+
+    #[test]
+    fn non_finite_losses_get_the_worst_finite_score()
+    {
+        let scores = ZScore::default().shape(&[1.0, 2.0, f32::INFINITY]);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(scores, vec![-half, half, half]);
+
+        for (name, shaper) in shapers()
+        {
+            let scores = shaper.shape(&[1.0, f32::NAN, 3.0, f32::INFINITY, 2.0]);
+            assert!(scores.iter().all(|s| s.is_finite()), "{}: {:?}", name, scores);
+            assert!(scores[0] < scores[4] && scores[4] < scores[2], "{}: {:?}", name, scores);
+            assert!(scores[1] >= scores[2] && scores[3] >= scores[2], "{}: {:?}", name, scores);
+
+            let scores = shaper.shape(&[f32::NAN, f32::INFINITY]);
+            assert!(scores.iter().all(|s| s.is_finite()), "{}: {:?}", name, scores);
+        }
+    }
+
+    #[test]
+    fn tiny_populations_are_finite()
+    {
+        for (name, shaper) in shapers()
+        {
+            assert!(shaper.shape(&[]).is_empty(), "{}", name);
+            assert_eq!(shaper.shape(&[2.0]), vec![0.0], "{}", name);
+        }
+    }
+
+    #[test]
+    fn lower_losses_get_lower_scores()
+    {
+        let losses = [4.0, 0.5, 1e9, 2.0, 0.5, 7.0];
+        for (name, shaper) in shapers()
+        {
+            let scores = shaper.shape(&losses);
+            assert!(scores.iter().all(|s| s.is_finite()), "{}: {:?}", name, scores);
+            assert_eq!(scores[1], scores[4], "{}: ties differ", name);
+            for i in 0..losses.len()
+            {
+                for j in 0..losses.len()
+                {
+                    if losses[i] < losses[j]
+                    {
+                        assert!(scores[i] <= scores[j], "{}: {:?}", name, scores);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rank_scores_sum_to_zero()
+    {
+        let losses: Vec<f32> = (0..50).map(|i| ((i * 37) % 50) as f32).collect();
+        for shaper in [&RankUtilities as &dyn FitnessShaper, &CenteredRank]
+        {
+            let total: f32 = shaper.shape(&losses).iter().sum();
+            assert!(total.abs() < 1e-5, "{}", total);
+        }
+    }
+
+    #[test]
+    fn ranks_ignore_outliers()
+    {
+        let plain = RankUtilities.shape(&[1.0, 2.0, 3.0, 4.0]);
+        let outlier = RankUtilities.shape(&[1.0, 2.0, 3.0, 1e30]);
+        assert_eq!(plain, outlier);
+
+        let utilities = RankUtilities.shape(&[3.0, 1.0, 2.0, 4.0]);
+        assert!(utilities[1] < utilities[2] && utilities[2] < 0.0);
+        assert!((utilities[0] - 0.25).abs() < 1e-6 && (utilities[3] - 0.25).abs() < 1e-6);
+    }
+}
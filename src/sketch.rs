@@ -0,0 +1,743 @@
+// A small language for program sketches, e.g.
+//
+//     if x ?{>,<,==} ??c { x ?{+,-,*,/} ??c } else { x ?{+,-,*,/} ??c }
+//
+// `??c` is a real constant learned by a `Normal` hole and `?{...}` is an operator chosen by
+// a `Categorical` hole. Operator choices bind like `+` and `-`, and `else if` chains are
+// allowed. Parsing adds the holes to a `HoleSet` in the order they appear, and every
+// assignment of them instantiates one concrete `Program`.
+use std::fmt;
+
+use crate::dist::{Categorical, Normal};
+use crate::estimator::GradientEstimator;
+use crate::expr::{BinOp, CmpOp, Expr, Program};
+use crate::hole::{Assignment, HoleId, HoleSet};
+
+// An operator written in the sketch, or one picked by choice hole `index` of the sketch
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpSlot<T>
+{
+    Fixed(T),
+    Choice(usize, Vec<T>)
+}
+
+// Sketch syntax tree. Variables index the sketch's variable list, constant and choice holes
+// index its own constant and choice holes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node
+{
+    Num(f32),
+    Var(usize),
+    Const(usize),
+    Neg(Box<Node>),
+    Binary(OpSlot<BinOp>, Box<Node>, Box<Node>),
+    If(OpSlot<CmpOp>, Box<Node>, Box<Node>, Box<Node>, Box<Node>)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError
+{
+    // Byte offset into the source
+    pub position: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{} at byte {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub struct Sketch
+{
+    root: Node,
+    variables: Vec<String>,
+    consts: Vec<HoleId<f32>>,
+    choices: Vec<HoleId<usize>>
+}
+
+impl Sketch
+{
+    // Parses `source` over the named input variables and, only if that succeeds, adds a
+    // `Normal(0, 1)` hole for every constant and a uniform `Categorical` for every choice
+    pub fn parse(source: &str, variables: &[&str], holes: &mut HoleSet, estimator: GradientEstimator) -> Result<Self, ParseError>
+    {
+        let tokens = lex(source)?;
+        let mut parser = Parser {tokens, pos: 0, variables, holes: Vec::new(), num_consts: 0, num_choices: 0};
+        let root = parser.expr()?;
+        if let Some(&(_, position)) = parser.tokens.get(parser.pos)
+        {
+            return Err(ParseError {position, message: "unexpected input after the program".to_owned()});
+        }
+
+        let mut consts = Vec::with_capacity(parser.num_consts);
+        let mut choices = Vec::with_capacity(parser.num_choices);
+        for hole in parser.holes
+        {
+            match hole
+            {
+                HoleKind::Const => consts.push(holes.add(Normal::new(0.0, 1.0))),
+                HoleKind::Choice(size) => choices.push(holes.add(Categorical::new(estimator, vec![0.0; size])))
+            }
+        }
+
+        Ok(Self {root, variables: variables.iter().map(|&v| v.to_owned()).collect(), consts, choices})
+    }
+
+    pub fn root(&self) -> &Node
+    {
+        &self.root
+    }
+
+    pub fn variables(&self) -> &[String]
+    {
+        &self.variables
+    }
+
+    pub fn const_hole(&self, index: usize) -> HoleId<f32>
+    {
+        self.consts[index]
+    }
+
+    pub fn choice_hole(&self, index: usize) -> HoleId<usize>
+    {
+        self.choices[index]
+    }
+
+    // The program `props` picks
+    pub fn instantiate(&self, props: &Assignment) -> Program
+    {
+        Program {expr: self.instantiate_node(&self.root, props), variables: self.variables.clone()}
+    }
+
+    fn resolve<T: Copy>(&self, slot: &OpSlot<T>, props: &Assignment) -> T
+    {
+        match slot
+        {
+            OpSlot::Fixed(op) => *op,
+            OpSlot::Choice(index, ops) => ops[props[self.choices[*index]]]
+        }
+    }
+
+    fn instantiate_node(&self, node: &Node, props: &Assignment) -> Expr
+    {
+        let boxed = |node: &Node| Box::new(self.instantiate_node(node, props));
+        match node
+        {
+            Node::Num(value) => Expr::Num(*value),
+            Node::Var(index) => Expr::Var(*index),
+            Node::Const(index) => Expr::Num(props[self.consts[*index]]),
+            Node::Neg(operand) => Expr::Neg(boxed(operand)),
+            Node::Binary(op, lhs, rhs) => Expr::Binary(self.resolve(op, props), boxed(lhs), boxed(rhs)),
+            Node::If(op, lhs, rhs, then, otherwise) =>
+            {
+                Expr::If(self.resolve(op, props), boxed(lhs), boxed(rhs), boxed(then), boxed(otherwise))
+            }
+        }
+    }
+}
+
+impl Program
+{
+    // Parses a program without holes
+    pub fn parse(source: &str, variables: &[&str]) -> Result<Self, ParseError>
+    {
+        let hole = lex(source)?.into_iter()
+            .find(|(token, _)| matches!(token, Token::ConstHole | Token::Choice(_)));
+        if let Some((_, position)) = hole
+        {
+            return Err(ParseError {position, message: "holes are not allowed in a program".to_owned()});
+        }
+
+        let mut holes = HoleSet::new();
+        let sketch = Sketch::parse(source, variables, &mut holes, GradientEstimator::Dnes)?;
+        Ok(sketch.instantiate(&holes.argmax()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Op
+{
+    Bin(BinOp),
+    Cmp(CmpOp)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token
+{
+    Num(f32),
+    Ident(String),
+    If,
+    Else,
+    ConstHole,
+    Choice(Vec<Op>),
+    Op(Op),
+    LBrace,
+    RBrace,
+    LParen,
+    RParen
+}
+
+impl fmt::Display for Token
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let symbol = |op: &Op| match op
+        {
+            Op::Bin(op) => op.symbol(),
+            Op::Cmp(op) => op.symbol()
+        };
+
+        match self
+        {
+            Token::Num(value) => write!(f, "`{}`", value),
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::If => write!(f, "`if`"),
+            Token::Else => write!(f, "`else`"),
+            Token::ConstHole => write!(f, "`??c`"),
+            Token::Choice(ops) => write!(f, "`?{{{}}}`", ops.iter().map(symbol).collect::<Vec<_>>().join(",")),
+            Token::Op(op) => write!(f, "`{}`", symbol(op)),
+            Token::LBrace => write!(f, "`{{`"),
+            Token::RBrace => write!(f, "`}}`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`")
+        }
+    }
+}
+
+fn lex_op(source: &str, at: usize) -> Option<(Op, usize)>
+{
+    const OPS: [(&str, Op); 10] = [
+        ("==", Op::Cmp(CmpOp::Eq)),
+        (">=", Op::Cmp(CmpOp::Ge)),
+        ("<=", Op::Cmp(CmpOp::Le)),
+        ("!=", Op::Cmp(CmpOp::Ne)),
+        (">", Op::Cmp(CmpOp::Gt)),
+        ("<", Op::Cmp(CmpOp::Lt)),
+        ("+", Op::Bin(BinOp::Add)),
+        ("-", Op::Bin(BinOp::Sub)),
+        ("*", Op::Bin(BinOp::Mul)),
+        ("/", Op::Bin(BinOp::Div))
+    ];
+
+    OPS.iter()
+        .find(|(text, _)| source[at..].starts_with(text))
+        .map(|(text, op)| (op.clone(), text.len()))
+}
+
+fn lex(source: &str) -> Result<Vec<(Token, usize)>, ParseError>
+{
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len()
+    {
+        let c = bytes[i] as char;
+        let start = i;
+
+        if c.is_whitespace()
+        {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() || (c == '.' && bytes.get(i + 1).is_some_and(|b| b.is_ascii_digit()))
+        {
+            while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.')
+            {
+                i += 1;
+            }
+            if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E')
+            {
+                i += 1;
+                if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-')
+                {
+                    i += 1;
+                }
+                while i < bytes.len() && bytes[i].is_ascii_digit()
+                {
+                    i += 1;
+                }
+            }
+
+            let value = source[start..i].parse().map_err(|_| ParseError {
+                position: start,
+                message: format!("malformed number `{}`", &source[start..i])
+            })?;
+            tokens.push((Token::Num(value), start));
+            continue;
+        }
+
+        if c.is_ascii_alphabetic() || c == '_'
+        {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_')
+            {
+                i += 1;
+            }
+
+            let token = match &source[start..i]
+            {
+                "if" => Token::If,
+                "else" => Token::Else,
+                name => Token::Ident(name.to_owned())
+            };
+            tokens.push((token, start));
+            continue;
+        }
+
+        if source[i..].starts_with("??c")
+        {
+            i += 3;
+            tokens.push((Token::ConstHole, start));
+            continue;
+        }
+
+        if source[i..].starts_with("?{")
+        {
+            i += 2;
+            let mut ops = Vec::new();
+            loop
+            {
+                while i < bytes.len() && (bytes[i] as char).is_whitespace()
+                {
+                    i += 1;
+                }
+
+                let (op, len) = lex_op(source, i).ok_or_else(|| ParseError {
+                    position: i,
+                    message: "expected an operator in the choice".to_owned()
+                })?;
+                ops.push(op);
+                i += len;
+
+                while i < bytes.len() && (bytes[i] as char).is_whitespace()
+                {
+                    i += 1;
+                }
+
+                match bytes.get(i)
+                {
+                    Some(b',') => i += 1,
+                    Some(b'}') =>
+                    {
+                        i += 1;
+                        break;
+                    }
+                    _ => return Err(ParseError {position: i, message: "expected `,` or `}` in the choice".to_owned()})
+                }
+            }
+
+            if !ops.iter().all(|op| matches!(op, Op::Bin(_))) && !ops.iter().all(|op| matches!(op, Op::Cmp(_)))
+            {
+                return Err(ParseError {position: start, message: "choice mixes arithmetic and comparison operators".to_owned()});
+            }
+
+            tokens.push((Token::Choice(ops), start));
+            continue;
+        }
+
+        let token = match c
+        {
+            '{' => Some(Token::LBrace),
+            '}' => Some(Token::RBrace),
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            _ => None
+        };
+
+        if let Some(token) = token
+        {
+            i += 1;
+            tokens.push((token, start));
+            continue;
+        }
+
+        match lex_op(source, i)
+        {
+            Some((op, len)) =>
+            {
+                i += len;
+                tokens.push((Token::Op(op), start));
+            }
+            None =>
+            {
+                let found = source[i..].chars().next().unwrap_or(c);
+                return Err(ParseError {position: start, message: format!("unexpected character `{}`", found)});
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+enum HoleKind
+{
+    Const,
+    // Number of operators to choose from
+    Choice(usize)
+}
+
+struct Parser<'a>
+{
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    variables: &'a [&'a str],
+    // Holes in the order they appear
+    holes: Vec<HoleKind>,
+    num_consts: usize,
+    num_choices: usize
+}
+
+impl Parser<'_>
+{
+    fn peek(&self) -> Option<&Token>
+    {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    // Byte offset of the next token, or of the end of the last one
+    fn position(&self) -> usize
+    {
+        match self.tokens.get(self.pos)
+        {
+            Some(&(_, position)) => position,
+            None => self.tokens.last().map_or(0, |&(_, position)| position + 1)
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ParseError>
+    {
+        let found = match self.peek()
+        {
+            Some(token) => token.to_string(),
+            None => "end of input".to_owned()
+        };
+        Err(ParseError {position: self.position(), message: format!("{}, found {}", message, found)})
+    }
+
+    fn expect(&mut self, token: Token, message: &str) -> Result<(), ParseError>
+    {
+        if self.peek() == Some(&token)
+        {
+            self.pos += 1;
+            return Ok(());
+        }
+        self.error(message)
+    }
+
+    fn expr(&mut self) -> Result<Node, ParseError>
+    {
+        if self.peek() != Some(&Token::If)
+        {
+            return self.sum();
+        }
+        self.pos += 1;
+
+        let lhs = self.sum()?;
+        let op = self.cmp_op()?;
+        let rhs = self.sum()?;
+
+        self.expect(Token::LBrace, "expected `{` after the condition")?;
+        let then = self.expr()?;
+        self.expect(Token::RBrace, "expected `}`")?;
+        self.expect(Token::Else, "expected `else`")?;
+
+        let otherwise = if self.peek() == Some(&Token::If)
+        {
+            self.expr()?
+        }
+        else
+        {
+            self.expect(Token::LBrace, "expected `{` after `else`")?;
+            let otherwise = self.expr()?;
+            self.expect(Token::RBrace, "expected `}`")?;
+            otherwise
+        };
+
+        Ok(Node::If(op, Box::new(lhs), Box::new(rhs), Box::new(then), Box::new(otherwise)))
+    }
+
+    fn cmp_op(&mut self) -> Result<OpSlot<CmpOp>, ParseError>
+    {
+        let slot = match self.peek()
+        {
+            Some(Token::Op(Op::Cmp(op))) => OpSlot::Fixed(*op),
+            Some(Token::Choice(ops)) =>
+            {
+                let ops: Option<Vec<CmpOp>> = ops.iter()
+                    .map(|op| if let Op::Cmp(op) = op {Some(*op)} else {None})
+                    .collect();
+                match ops
+                {
+                    Some(ops) => self.choice(ops),
+                    None => return self.error("expected a choice of comparisons")
+                }
+            }
+            _ => return self.error("expected a comparison")
+        };
+
+        self.pos += 1;
+        Ok(slot)
+    }
+
+    fn choice<T>(&mut self, ops: Vec<T>) -> OpSlot<T>
+    {
+        self.holes.push(HoleKind::Choice(ops.len()));
+        self.num_choices += 1;
+        OpSlot::Choice(self.num_choices - 1, ops)
+    }
+
+    fn sum(&mut self) -> Result<Node, ParseError>
+    {
+        let mut lhs = self.product()?;
+        loop
+        {
+            let op = match self.peek()
+            {
+                Some(Token::Op(Op::Bin(op))) if matches!(op, BinOp::Add | BinOp::Sub) => OpSlot::Fixed(*op),
+                Some(Token::Choice(ops)) if ops.iter().all(|op| matches!(op, Op::Bin(_))) =>
+                {
+                    let ops = ops.iter()
+                        .filter_map(|op| if let Op::Bin(op) = op {Some(*op)} else {None})
+                        .collect();
+                    self.choice(ops)
+                }
+                _ => return Ok(lhs)
+            };
+
+            self.pos += 1;
+            let rhs = self.product()?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn product(&mut self) -> Result<Node, ParseError>
+    {
+        let mut lhs = self.unary()?;
+        loop
+        {
+            let op = match self.peek()
+            {
+                Some(Token::Op(Op::Bin(op))) if matches!(op, BinOp::Mul | BinOp::Div) => *op,
+                _ => return Ok(lhs)
+            };
+
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = Node::Binary(OpSlot::Fixed(op), Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ParseError>
+    {
+        if self.peek() == Some(&Token::Op(Op::Bin(BinOp::Sub)))
+        {
+            self.pos += 1;
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Node, ParseError>
+    {
+        let node = match self.peek()
+        {
+            Some(Token::Num(value)) => Node::Num(*value),
+            Some(Token::ConstHole) =>
+            {
+                self.holes.push(HoleKind::Const);
+                self.num_consts += 1;
+                Node::Const(self.num_consts - 1)
+            }
+            Some(Token::Ident(name)) =>
+            {
+                match self.variables.iter().position(|v| v == name)
+                {
+                    Some(index) => Node::Var(index),
+                    None => return self.error("unknown variable")
+                }
+            }
+            Some(Token::LParen) =>
+            {
+                self.pos += 1;
+                let inner = self.expr()?;
+                self.expect(Token::RParen, "expected `)`")?;
+                return Ok(inner);
+            }
+            _ => return self.error("expected a number, variable, `??c` or `(`")
+        };
+
+        self.pos += 1;
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::rng::RNG;
+
+    fn parse(source: &str) -> Node
+    {
+        let mut holes = HoleSet::new();
+        match Sketch::parse(source, &["x"], &mut holes, GradientEstimator::Dnes)
+        {
+            Ok(sketch) => sketch.root().clone(),
+            Err(error) => panic!("`{}`: {}", source, error)
+        }
+    }
+
+    fn num(value: f32) -> Box<Node>
+    {
+        Box::new(Node::Num(value))
+    }
+
+    fn x() -> Box<Node>
+    {
+        Box::new(Node::Var(0))
+    }
+
+    #[test]
+    fn choices_associate_to_the_left()
+    {
+        let add_sub = OpSlot::Choice(0, vec![BinOp::Add, BinOp::Sub]);
+        let mul_div = OpSlot::Choice(1, vec![BinOp::Mul, BinOp::Div]);
+        let inner = Node::Binary(add_sub.clone(), Box::new(Node::Const(0)), x());
+        assert_eq!(parse("??c ?{+,-} x ?{*,/} x"), Node::Binary(mul_div, Box::new(inner), x()));
+
+        // A choice binds like `+` even when it offers `*`
+        let product = Node::Binary(OpSlot::Fixed(BinOp::Mul), x(), num(2.0));
+        assert_eq!(parse("x ?{+,-} x * 2"), Node::Binary(add_sub, x(), Box::new(product)));
+    }
+
+    #[test]
+    fn else_if_nests_in_the_else_branch()
+    {
+        let inner = Node::If(OpSlot::Fixed(CmpOp::Lt), x(), num(0.0), Box::new(Node::Neg(num(1.0))), num(0.0));
+        assert_eq!(
+            parse("if x > 0 { 1 } else if x < 0 { -1 } else { 0 }"),
+            Node::If(OpSlot::Fixed(CmpOp::Gt), x(), num(0.0), num(1.0), Box::new(inner))
+        );
+    }
+
+    #[test]
+    fn unary_minus_binds_tighter_than_products()
+    {
+        let neg_x = Box::new(Node::Neg(x()));
+        assert_eq!(parse("-x * 2"), Node::Binary(OpSlot::Fixed(BinOp::Mul), neg_x.clone(), num(2.0)));
+        assert_eq!(parse("2 - -x"), Node::Binary(OpSlot::Fixed(BinOp::Sub), num(2.0), neg_x.clone()));
+        assert_eq!(parse("--x"), Node::Neg(neg_x));
+    }
+
+    #[test]
+    fn numbers_take_exponents()
+    {
+        assert_eq!(parse("1e3"), Node::Num(1e3));
+        assert_eq!(parse("2.5E-2"), Node::Num(2.5e-2));
+        assert_eq!(parse("1e+2"), Node::Num(1e2));
+        assert_eq!(parse(".5"), Node::Num(0.5));
+        assert_eq!(parse("2e1-x"), Node::Binary(OpSlot::Fixed(BinOp::Sub), num(20.0), x()));
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_offending_input()
+    {
+        let cases = [
+            ("x + $", 4, "unexpected character"),
+            ("1.2.3", 0, "malformed number"),
+            ("1e", 0, "malformed number"),
+            ("x ?{}", 4, "expected an operator"),
+            ("x ?{+ 1", 6, "expected `,` or `}`"),
+            ("x ?{+,>} 1", 2, "mixes"),
+            ("y + 1", 0, "unknown variable"),
+            ("x +", 3, "expected a number"),
+            ("x 1", 2, "unexpected input after the program"),
+            ("(x + 1", 6, "expected `)`"),
+            ("if x 1 { x } else { 1 }", 5, "expected a comparison"),
+            ("if x ?{+} 1 { x } else { 1 }", 12, "expected a comparison, found `{`"),
+            ("if x > 1 x } else { 1 }", 9, "expected `{` after the condition"),
+            ("if x > 1 { x }", 14, "expected `else`")
+        ];
+
+        for (source, position, message) in cases
+        {
+            let mut holes = HoleSet::new();
+            let error = Sketch::parse(source, &["x"], &mut holes, GradientEstimator::Dnes).err()
+                .unwrap_or_else(|| panic!("`{}` parsed", source));
+            assert_eq!(error.position, position, "`{}`: {}", source, error);
+            assert!(error.message.contains(message), "`{}`: {}", source, error);
+        }
+
+        let error = Program::parse("x + ??c", &["x"]).unwrap_err();
+        assert_eq!(error.position, 4);
+    }
+
+    #[test]
+    fn holes_are_added_in_source_order()
+    {
+        let mut holes = HoleSet::new();
+        holes.add(Normal::new(0.0, 1.0));
+
+        let source = "if x ?{>,<} ??c { ??c ?{+,-,*} x } else { x ?{+,-} ??c }";
+        let sketch = Sketch::parse(source, &["x"], &mut holes, GradientEstimator::Dnes).unwrap();
+        assert_eq!(holes.len(), 7);
+
+        let choices: Vec<usize> = (0..3).map(|i| sketch.choice_hole(i).index()).collect();
+        let consts: Vec<usize> = (0..3).map(|i| sketch.const_hole(i).index()).collect();
+        assert_eq!(choices, vec![1, 4, 5]);
+        assert_eq!(consts, vec![2, 3, 6]);
+
+        let sizes: Vec<usize> = (0..3)
+            .map(|i| holes.dist::<Categorical>(sketch.choice_hole(i)).unwrap().logits().len())
+            .collect();
+        assert_eq!(sizes, vec![2, 3, 2]);
+
+        // A sketch that fails to parse adds no holes
+        assert!(Sketch::parse("??c ?{+,-} ??c +", &["x"], &mut holes, GradientEstimator::Dnes).is_err());
+        assert_eq!(holes.len(), 7);
+    }
+
+    // Printing a program and parsing it back gives a program that prints and evaluates the
+    // same. The trees may differ, e.g. a negative constant parses back as a negation.
+    #[test]
+    fn printed_programs_parse_back()
+    {
+        let sources = [
+            "x - (1 - x)",
+            "(x - 1) - x",
+            "x / (x * 2) / 3",
+            "-(x + 1) * -x",
+            "(if x > 1 { x } else { 1 }) + 1",
+            "if x >= 0 { 1 } else if x != -1 { 2 } else { if x < 0 { 3 } else { 4 } }",
+            "x ?{+,-,*,/} ??c ?{*,/} (??c ?{-,/} x)",
+            "if ??c ?{>,<,==} x { -??c } else { ??c ?{+,-} x * ??c }"
+        ];
+        let inputs = [-2.0, -0.5, 0.0, 1.0, 3.0];
+        let mut rng = RNG::new(7);
+
+        for source in sources
+        {
+            let mut holes = HoleSet::new();
+            let sketch = Sketch::parse(source, &["x"], &mut holes, GradientEstimator::Dnes).unwrap();
+            for _ in 0..20
+            {
+                let program = sketch.instantiate(&holes.sample(&mut rng));
+                let printed = program.to_string();
+                let parsed = Program::parse(&printed, &["x"]).unwrap_or_else(|error| panic!("`{}`: {}", printed, error));
+
+                assert_eq!(parsed.to_string(), printed);
+                for input in inputs
+                {
+                    let (expected, actual) = (program.eval(&[input]), parsed.eval(&[input]));
+                    assert!(expected == actual || (expected.is_nan() && actual.is_nan()), "`{}` at {}: {} != {}", printed, input, expected, actual);
+                }
+            }
+        }
+    }
+}
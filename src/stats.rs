@@ -0,0 +1,136 @@
+// Distribution functions used by the statistical tests
+
+pub fn ln_gamma(x: f64) -> f64
+{
+    // Lanczos approximation, g = 7, n = 9
+    const COEFFS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7
+    ];
+
+    if x < 0.5
+    {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let mut sum = COEFFS[0];
+    for (i, c) in COEFFS.iter().enumerate().skip(1)
+    {
+        sum += c / (x + i as f64);
+    }
+
+    let t = x + 7.5;
+    0.5 * std::f64::consts::TAU.ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// Regularized upper incomplete gamma function Q(a, x)
+pub fn gamma_q(a: f64, x: f64) -> f64
+{
+    if x <= 0.0
+    {
+        return 1.0;
+    }
+
+    let ln_prefix = a * x.ln() - x - ln_gamma(a);
+
+    if x < a + 1.0
+    {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+        for _ in 0..1000
+        {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15
+            {
+                break;
+            }
+        }
+
+        return 1.0 - sum * ln_prefix.exp();
+    }
+
+    // Lentz's continued fraction
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000
+    {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {d = tiny;}
+        c = b + an / c;
+        if c.abs() < tiny {c = tiny;}
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15
+        {
+            break;
+        }
+    }
+
+    ln_prefix.exp() * h
+}
+
+// P(X >= stat) for X ~ chi-square with `dof` degrees of freedom
+pub fn chi_square_sf(stat: f64, dof: f64) -> f64
+{
+    gamma_q(0.5 * dof, 0.5 * stat)
+}
+
+// Pearson's chi-square p-value of observed counts against expected counts, pooling
+// cells whose expectation is below 5 into a single cell
+pub fn chi_square_test(observed: &[u64], expected: &[f64]) -> f64
+{
+    let mut stat = 0.0;
+    let mut cells = 0;
+    let mut pooled_observed = 0.0;
+    let mut pooled_expected = 0.0;
+
+    for (&o, &e) in observed.iter().zip(expected.iter())
+    {
+        if e < 5.0
+        {
+            pooled_observed += o as f64;
+            pooled_expected += e;
+            continue;
+        }
+
+        stat += (o as f64 - e).powi(2) / e;
+        cells += 1;
+    }
+
+    if pooled_expected > 0.0
+    {
+        stat += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+        cells += 1;
+    }
+
+    chi_square_sf(stat, (cells - 1) as f64)
+}
+
+// Two-sided p-value of a standard normal statistic, erfc(|z| / sqrt(2)) = Q(1/2, z^2 / 2)
+pub fn normal_two_sided(z: f64) -> f64
+{
+    gamma_q(0.5, 0.5 * z * z)
+}
+
+pub fn poisson_pmf(k: u64, lambda: f64) -> f64
+{
+    (k as f64 * lambda.ln() - lambda - ln_gamma(k as f64 + 1.0)).exp()
+}
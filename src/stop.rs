@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+// Criteria that end a run before `num_iters`, all checked after every iteration so the
+// evaluation budget can be overshot by less than one population
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stopping
+{
+    // A sample with a loss at or below this solves the task
+    pub target_loss: Option<f32>,
+    pub max_evaluations: Option<usize>,
+    pub max_duration: Option<Duration>,
+    // Iterations without a new best sample
+    pub patience: Option<usize>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason
+{
+    Iterations,
+    TargetLoss,
+    Evaluations,
+    WallClock,
+    Patience
+}
+
+// Iterations and evaluations it took a sample to reach the target loss, both counting the
+// ones that found it, so they read like the totals of `RunResult`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Solution
+{
+    pub iters: usize,
+    pub evaluations: usize
+}
+
+impl Stopping
+{
+    pub fn check(&self, solved: bool, evaluations: usize, elapsed: Duration, iters_without_improvement: usize) -> Option<StopReason>
+    {
+        if solved && self.target_loss.is_some()
+        {
+            return Some(StopReason::TargetLoss);
+        }
+
+        if self.max_evaluations.is_some_and(|max| evaluations >= max)
+        {
+            return Some(StopReason::Evaluations);
+        }
+
+        if self.max_duration.is_some_and(|max| elapsed >= max)
+        {
+            return Some(StopReason::WallClock);
+        }
+
+        if self.patience.is_some_and(|patience| iters_without_improvement >= patience)
+        {
+            return Some(StopReason::Patience);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn unset_criteria_never_stop()
+    {
+        let stopping = Stopping::default();
+        assert_eq!(stopping.check(true, usize::MAX, Duration::MAX, usize::MAX), None);
+    }
+
+    #[test]
+    fn each_criterion_stops_at_its_limit()
+    {
+        let stopping = Stopping {
+            target_loss: Some(0.0),
+            max_evaluations: Some(100),
+            max_duration: Some(Duration::from_secs(10)),
+            patience: Some(5)
+        };
+        let zero = Duration::ZERO;
+
+        assert_eq!(stopping.check(false, 99, Duration::from_secs(9), 4), None);
+        assert_eq!(stopping.check(true, 0, zero, 0), Some(StopReason::TargetLoss));
+        assert_eq!(stopping.check(false, 100, zero, 0), Some(StopReason::Evaluations));
+        assert_eq!(stopping.check(false, 0, Duration::from_secs(10), 0), Some(StopReason::WallClock));
+        assert_eq!(stopping.check(false, 0, zero, 5), Some(StopReason::Patience));
+
+        // Solving takes precedence, then the budgets in the order they are declared
+        assert_eq!(stopping.check(true, 100, Duration::from_secs(10), 5), Some(StopReason::TargetLoss));
+        assert_eq!(stopping.check(false, 100, Duration::from_secs(10), 5), Some(StopReason::Evaluations));
+        assert_eq!(stopping.check(false, 0, Duration::from_secs(10), 5), Some(StopReason::WallClock));
+    }
+}
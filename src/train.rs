@@ -0,0 +1,342 @@
+use std::time::Instant;
+
+use crate::hole::{Assignment, HoleSet};
+use crate::optim::{Optimizer, Sgd};
+use crate::restart::Restarts;
+use crate::rng::RandomSource;
+use crate::sampling::Sampling;
+use crate::schedule::{ConstantRate, FixedPopulation, PopulationSchedule, RateSchedule};
+use crate::shaping::{FitnessShaper, ZScore};
+use crate::stop::{Solution, StopReason, Stopping};
+
+// Sample/score/shape/grad/update loop shared by every experiment. `num_mutations` and
+// `rate` are the base values the schedules scale from.
+pub struct Trainer
+{
+    pub num_mutations: usize,
+    pub num_iters: usize,
+    pub rate: f32,
+    pub sampling: Sampling,
+    pub shaper: Box<dyn FitnessShaper>,
+    pub optimizer: Box<dyn Optimizer>,
+    pub rate_schedule: Box<dyn RateSchedule>,
+    pub population_schedule: Box<dyn PopulationSchedule>,
+    // Restarts the holes from their initial distributions when the search stagnates
+    pub restarts: Option<Restarts>,
+    // Weight of the entropy of the discrete holes, subtracted from the loss
+    pub entropy_bonus: f32,
+    // Number of best samples kept in the hall of fame
+    pub hall_of_fame_size: usize,
+    pub stopping: Stopping
+}
+
+// What happened at one iteration
+#[derive(Clone, Copy, Debug)]
+pub struct IterationLog
+{
+    pub iter: usize,
+    // Mean loss of the population
+    pub loss: f32,
+    pub rate: f32,
+    pub population: usize
+}
+
+// A sampled assignment, its loss and the iteration it was first sampled at
+#[derive(Clone)]
+pub struct FameEntry
+{
+    pub assignment: Assignment,
+    pub loss: f32,
+    pub iter: usize
+}
+
+// The lowest-loss assignments sampled so far, best first. An assignment sampled again is
+// only kept the first time, distinct assignments with equal losses are all kept.
+pub struct HallOfFame
+{
+    capacity: usize,
+    entries: Vec<FameEntry>
+}
+
+impl HallOfFame
+{
+    pub fn new(capacity: usize) -> Self
+    {
+        Self {capacity, entries: Vec::with_capacity(capacity)}
+    }
+
+    pub fn entries(&self) -> &[FameEntry]
+    {
+        &self.entries
+    }
+
+    pub fn best(&self) -> Option<&FameEntry>
+    {
+        self.entries.first()
+    }
+
+    pub fn offer(&mut self, assignment: &Assignment, loss: f32, iter: usize)
+    {
+        if loss.is_nan() || self.capacity == 0
+        {
+            return;
+        }
+
+        if self.entries.len() == self.capacity && loss >= self.entries[self.capacity - 1].loss
+        {
+            return;
+        }
+
+        if self.entries.iter().any(|entry| entry.assignment == *assignment)
+        {
+            return;
+        }
+
+        let position = self.entries.partition_point(|entry| entry.loss < loss);
+        self.entries.insert(position, FameEntry {assignment: assignment.clone(), loss, iter});
+        self.entries.truncate(self.capacity);
+    }
+}
+
+pub struct RunResult
+{
+    pub log: Vec<IterationLog>,
+    // Best samples of the whole run, kept across restarts
+    pub hall_of_fame: HallOfFame,
+    // Iterations after which the holes were restarted
+    pub restarts: Vec<usize>,
+    pub stop_reason: StopReason,
+    // Objective evaluations over the whole run
+    pub evaluations: usize,
+    // First sample that reached `Stopping::target_loss`
+    pub solution: Option<Solution>
+}
+
+impl Trainer
+{
+    pub fn new(num_mutations: usize, num_iters: usize, rate: f32) -> Self
+    {
+        Self {
+            num_mutations,
+            num_iters,
+            rate,
+            sampling: Sampling::Independent,
+            shaper: Box::new(ZScore::default()),
+            optimizer: Box::new(Sgd),
+            rate_schedule: Box::new(ConstantRate),
+            population_schedule: Box::new(FixedPopulation),
+            restarts: None,
+            entropy_bonus: 0.0,
+            hall_of_fame_size: 5,
+            stopping: Stopping::default()
+        }
+    }
+
+    // Minimises `objective` over the holes. Schedules count iterations from the latest restart.
+    pub fn run<R, F>(&mut self, holes: &mut HoleSet, rng: &mut R, mut objective: F) -> RunResult
+    where
+        R: RandomSource,
+        F: FnMut(&Assignment) -> f32
+    {
+        let mut result = RunResult {
+            log: Vec::with_capacity(self.num_iters),
+            hall_of_fame: HallOfFame::new(self.hall_of_fame_size),
+            restarts: Vec::new(),
+            stop_reason: StopReason::Iterations,
+            evaluations: 0,
+            solution: None
+        };
+        self.optimizer.reset();
+        self.rate_schedule.reset();
+        if let Some(restarts) = self.restarts.as_mut()
+        {
+            restarts.reset();
+        }
+
+        let mut base = self.num_mutations;
+        let mut start = 0;
+        let mut run_best = f32::INFINITY;
+        let mut since_improvement = 0;
+        let started = Instant::now();
+        let mut best = f32::INFINITY;
+        let mut since_best = 0;
+
+        for iter in 0..self.num_iters
+        {
+            let size = self.population_schedule.size(iter - start, base);
+            let rate = self.rate_schedule.rate(iter - start, self.rate);
+
+            let population = holes.sample_population(size, self.sampling, rng);
+            let losses: Vec<f32> = population.iter().map(&mut objective).collect();
+            let loss = losses.iter().sum::<f32>() / size as f32;
+
+            for (j, (props, &sample_loss)) in population.iter().zip(losses.iter()).enumerate()
+            {
+                result.hall_of_fame.offer(props, sample_loss, iter);
+                if result.solution.is_none() && self.stopping.target_loss.is_some_and(|target| sample_loss <= target)
+                {
+                    result.solution = Some(Solution {iters: iter + 1, evaluations: result.evaluations + j + 1});
+                }
+            }
+            result.evaluations += size;
+
+            let iter_best = losses.iter().copied().fold(f32::INFINITY, f32::min);
+            if iter_best < best
+            {
+                best = iter_best;
+                since_best = 0;
+            }
+            else
+            {
+                since_best += 1;
+            }
+
+            let scores = self.shaper.shape(&losses);
+            holes.step_with(&population, &scores, rate, self.optimizer.as_mut(), self.entropy_bonus);
+            self.rate_schedule.observe(loss);
+            result.log.push(IterationLog {iter, loss, rate, population: size});
+
+            if let Some(restarts) = self.restarts.as_mut()
+            {
+                restarts.record(size);
+                if loss < run_best
+                {
+                    run_best = loss;
+                    since_improvement = 0;
+                }
+                else
+                {
+                    since_improvement += 1;
+                }
+
+                if restarts.stagnated(&holes.discrete_entropies(), since_improvement)
+                {
+                    holes.reset();
+                    base = restarts.restart(self.num_mutations, rng);
+                    start = iter + 1;
+                    run_best = f32::INFINITY;
+                    since_improvement = 0;
+                    self.optimizer.reset();
+                    self.rate_schedule.reset();
+                    result.restarts.push(iter);
+                }
+            }
+
+            let solved = result.solution.is_some();
+            if let Some(reason) = self.stopping.check(solved, result.evaluations, started.elapsed(), since_best)
+            {
+                result.stop_reason = reason;
+                break;
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::Duration;
+
+    use super::*;
+    use crate::dist::Categorical;
+    use crate::estimator::GradientEstimator;
+    use crate::rng::RNG;
+
+    // Assignment of a single categorical hole that always takes `category`
+    fn assignment(category: usize) -> Assignment
+    {
+        let mut logits = vec![f32::NEG_INFINITY; 4];
+        logits[category] = 0.0;
+        let mut holes = HoleSet::new();
+        holes.add(Categorical::new(GradientEstimator::Dnes, logits));
+        holes.argmax()
+    }
+
+    #[test]
+    fn hall_of_fame_keeps_distinct_assignments_with_equal_losses()
+    {
+        let mut fame = HallOfFame::new(3);
+        fame.offer(&assignment(0), 1.0, 0);
+        fame.offer(&assignment(1), 1.0, 1);
+        fame.offer(&assignment(0), 1.0, 2);
+        fame.offer(&assignment(2), 0.0, 3);
+        fame.offer(&assignment(3), 1.0, 4);
+
+        let iters: Vec<usize> = fame.entries().iter().map(|entry| entry.iter).collect();
+        assert_eq!(iters, vec![3, 1, 0]);
+        assert!(fame.best().unwrap().assignment == assignment(2));
+    }
+
+    // A run over one hole of four categories
+    fn run(trainer: &mut Trainer, mut objective: impl FnMut(usize) -> f32) -> RunResult
+    {
+        let mut holes = HoleSet::new();
+        let id = holes.add(Categorical::new(GradientEstimator::Dnes, vec![0.0; 4]));
+        trainer.run(&mut holes, &mut RNG::new(1), |props| objective(props[id]))
+    }
+
+    #[test]
+    fn runs_without_criteria_use_every_iteration()
+    {
+        let result = run(&mut Trainer::new(10, 7, 0.1), |_| 1.0);
+        assert_eq!(result.stop_reason, StopReason::Iterations);
+        assert_eq!(result.log.len(), 7);
+        assert_eq!(result.evaluations, 70);
+        assert_eq!(result.solution, None);
+    }
+
+    #[test]
+    fn reaching_the_target_stops_and_counts_the_solving_sample()
+    {
+        let mut trainer = Trainer::new(10, 1000, 0.1);
+        trainer.stopping.target_loss = Some(0.0);
+
+        // The category is the loss, every evaluation is kept to find the first that solved
+        // the task
+        let mut losses = Vec::new();
+        let result = run(&mut trainer, |category| {
+            losses.push(category as f32);
+            category as f32
+        });
+
+        let first = losses.iter().position(|&loss| loss == 0.0).unwrap();
+        let solution = result.solution.unwrap();
+        assert_eq!(result.stop_reason, StopReason::TargetLoss);
+        assert_eq!(solution.evaluations, first + 1);
+        assert_eq!(solution.iters, first / 10 + 1);
+        assert_eq!(solution.iters, result.log.len());
+    }
+
+    #[test]
+    fn evaluation_budget_is_overshot_by_less_than_a_population()
+    {
+        let mut trainer = Trainer::new(10, 1000, 0.1);
+        trainer.stopping.max_evaluations = Some(95);
+        let result = run(&mut trainer, |_| 1.0);
+        assert_eq!(result.stop_reason, StopReason::Evaluations);
+        assert_eq!(result.evaluations, 100);
+        assert_eq!(result.log.len(), 10);
+    }
+
+    #[test]
+    fn patience_counts_iterations_without_a_new_best_sample()
+    {
+        let mut trainer = Trainer::new(10, 1000, 0.1);
+        trainer.stopping.patience = Some(5);
+        let result = run(&mut trainer, |_| 1.0);
+        assert_eq!(result.stop_reason, StopReason::Patience);
+        assert_eq!(result.log.len(), 6);
+    }
+
+    #[test]
+    fn wall_clock_is_checked_after_every_iteration()
+    {
+        let mut trainer = Trainer::new(10, 1000, 0.1);
+        trainer.stopping.max_duration = Some(Duration::ZERO);
+        let result = run(&mut trainer, |_| 1.0);
+        assert_eq!(result.stop_reason, StopReason::WallClock);
+        assert_eq!(result.log.len(), 1);
+    }
+}